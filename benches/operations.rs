@@ -0,0 +1,64 @@
+//! Timings of the per site operations on a large supercell
+//!
+//! Run them with and without the `parallel` feature to see the speedup:
+//!
+//! ```sh
+//! cargo bench --bench operations
+//! cargo bench --bench operations --features parallel
+//! ```
+
+use rand::{SeedableRng, rngs::StdRng};
+use std::{
+    hint::black_box,
+    path::Path,
+    time::{Duration, Instant},
+};
+use vegas_lattice::{Alloy, Lattice, Mask};
+
+const RUNS: usize = 5;
+const CELLS: usize = 60;
+
+/// Runs `f` a few times on the input built by `setup` and reports the best
+/// time, the setup is not timed
+fn bench<I, T>(name: &str, mut setup: impl FnMut() -> I, mut f: impl FnMut(I) -> T) {
+    let mut best = Duration::MAX;
+    for _ in 0..RUNS {
+        let input = setup();
+        let start = Instant::now();
+        black_box(f(input));
+        best = best.min(start.elapsed());
+    }
+    println!("{:<8} {:>10.2} ms", name, best.as_secs_f64() * 1e3);
+}
+
+fn main() {
+    let unit = Lattice::bcc(1.0).try_with_neighbor_shells(2).unwrap();
+    let lattice = unit.clone().expand_all(CELLS);
+    println!(
+        "bcc {}x{}x{}: {} sites, {} edges, parallel: {}",
+        CELLS,
+        CELLS,
+        CELLS,
+        lattice.sites().len(),
+        lattice.edges().len(),
+        cfg!(feature = "parallel")
+    );
+
+    bench("expand", || unit.clone(), |unit| unit.expand_all(CELLS));
+    bench(
+        "alloy",
+        || {
+            let alloy = Alloy::try_new(vec!["Fe", "Ni"], vec![1, 1]).unwrap();
+            (lattice.clone(), alloy)
+        },
+        |(lattice, alloy)| lattice.alloy_sites("A", alloy, &mut StdRng::seed_from_u64(0)),
+    );
+    bench(
+        "mask",
+        || {
+            let mask = Mask::try_new(Path::new("docs/pattern.png"), 1.0).unwrap();
+            (lattice.clone(), mask)
+        },
+        |(lattice, mask)| lattice.apply_mask_z(mask, &mut StdRng::seed_from_u64(0)),
+    );
+}
@@ -1,7 +1,10 @@
 //! Let's abstract an alloy
 
+use crate::Selector;
 use crate::error::{Result, VegasLatticeError};
 use rand::{Rng, distr::Distribution, distr::weighted::WeightedIndex};
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
 
 /// An alloy is a collection of kinds of atoms and their ratios
 ///
@@ -20,18 +23,30 @@ use rand::{Rng, distr::Distribution, distr::weighted::WeightedIndex};
 #[derive(Debug)]
 pub struct Alloy {
     kinds: Vec<String>,
-    weights: WeightedIndex<u32>,
+    ratios: Vec<f64>,
+    tags: Vec<Vec<String>>,
+    weights: WeightedIndex<f64>,
 }
 
 impl Alloy {
     /// Create a new alloy with a given list of kinds and their ratios
     pub fn try_new(kinds: Vec<&str>, ratios: Vec<u32>) -> Result<Self> {
+        Self::try_from_fractions(kinds, ratios.into_iter().map(f64::from).collect())
+    }
+
+    /// Create a new alloy with a given list of kinds and their ratios, which
+    /// may be fractional
+    pub fn try_from_fractions(kinds: Vec<&str>, ratios: Vec<f64>) -> Result<Self> {
         if kinds.len() != ratios.len() {
             return Err(VegasLatticeError::InvalidRatios);
         }
-        let kinds = kinds.into_iter().map(|s| s.to_owned()).collect();
         let weights = WeightedIndex::new(&ratios)?;
-        Ok(Self { kinds, weights })
+        Ok(Self {
+            tags: vec![Vec::new(); kinds.len()],
+            kinds: kinds.into_iter().map(String::from).collect(),
+            ratios,
+            weights,
+        })
     }
 
     pub fn try_from_targets(targets: Vec<(&str, u32)>) -> Result<Self> {
@@ -39,15 +54,229 @@ impl Alloy {
         Self::try_new(kinds, ratios)
     }
 
+    /// Create the alloy described by a specification, the substituted sites
+    /// get the tags of their target
+    pub fn try_from_spec(spec: &AlloySpec) -> Result<Self> {
+        spec.validate()?;
+        let kinds = spec.targets.iter().map(|t| t.kind.as_str()).collect();
+        let ratios = spec.targets.iter().map(|t| t.fraction).collect();
+        let mut alloy = Self::try_from_fractions(kinds, ratios)?;
+        alloy.tags = spec.targets.iter().map(|t| t.tags.clone()).collect();
+        Ok(alloy)
+    }
+
     /// Picks a kind of atom from the alloy
     pub fn pick<R: Rng>(&self, rng: &mut R) -> &str {
-        &self.kinds[self.weights.sample(rng)]
+        &self.kinds[self.pick_index(rng)]
+    }
+
+    /// Picks a kind of atom from the alloy, as an index into its kinds
+    pub(crate) fn pick_index<R: Rng>(&self, rng: &mut R) -> usize {
+        self.weights.sample(rng)
+    }
+
+    /// The kinds of atoms of the alloy
+    pub(crate) fn kinds(&self) -> &[String] {
+        &self.kinds
+    }
+
+    /// Tags given to the sites substituted with the kind at `index`
+    pub(crate) fn tags(&self, index: usize) -> &[String] {
+        &self.tags[index]
+    }
+
+    /// Splits `total` sites among the kinds of the alloy in exact proportion
+    /// to their ratios, following the order of the kinds.
+    ///
+    /// Every kind gets the integer part of its share, and the sites left are
+    /// given one by one to the kinds with the largest fractional parts, ties
+    /// going to the kind listed first.
+    ///
+    /// ```rust
+    /// use vegas_lattice::Alloy;
+    ///
+    /// let alloy = Alloy::try_new(vec!["Fe", "Ni", "Co"], vec![1, 1, 1]).unwrap();
+    /// assert_eq!(alloy.counts(10), vec![4, 3, 3]);
+    /// ```
+    pub fn counts(&self, total: usize) -> Vec<usize> {
+        let sum: f64 = self.ratios.iter().sum();
+        let shares: Vec<_> = self
+            .ratios
+            .iter()
+            .map(|&ratio| total as f64 * ratio / sum)
+            .collect();
+        let mut counts: Vec<_> = shares.iter().map(|share| share.floor() as usize).collect();
+        let mut order: Vec<_> = (0..counts.len()).collect();
+        // Stable sort, so equal remainders keep the order of the kinds
+        order.sort_by(|&i, &j| {
+            let remainder = |k: usize| shares[k] - shares[k].floor();
+            remainder(j).total_cmp(&remainder(i))
+        });
+        let left = total.saturating_sub(counts.iter().sum::<usize>());
+        for &i in order.iter().take(left) {
+            counts[i] += 1;
+        }
+        counts
+    }
+
+    /// Lists the indices of the kinds of the alloy, each repeated as many
+    /// times as given by `counts(total)`
+    pub(crate) fn exact_indices(&self, total: usize) -> Vec<usize> {
+        self.counts(total)
+            .into_iter()
+            .enumerate()
+            .flat_map(|(index, count)| std::iter::repeat_n(index, count))
+            .collect()
+    }
+}
+
+/// One of the kinds an alloy specification substitutes sites with
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TargetSpec {
+    kind: String,
+    fraction: f64,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+}
+
+impl TargetSpec {
+    /// A target kind with its fraction of the substituted sites
+    pub fn new(kind: &str, fraction: f64) -> Self {
+        TargetSpec {
+            kind: kind.to_string(),
+            fraction,
+            tags: Vec::new(),
+        }
+    }
+
+    /// Adds tags to the sites substituted with this kind
+    pub fn with_tags(mut self, tags: Vec<&str>) -> Self {
+        self.tags = tags.iter().map(|tag| tag.to_string()).collect();
+        self
+    }
+}
+
+/// Specification of an alloy, the kinds of the sites to substitute and the
+/// kinds to substitute them with, as read from JSON or TOML files.
+///
+/// Fractions are relative, they need not add up to one.
+///
+/// # Example
+///
+/// ```rust
+/// use vegas_lattice::{Alloy, AlloySpec, Lattice};
+///
+/// let spec = AlloySpec::from_str_toml(r#"
+///     sources = ["A", "B"]
+///
+///     [[targets]]
+///     kind = "Fe"
+///     fraction = 0.75
+///
+///     [[targets]]
+///     kind = "Ni"
+///     fraction = 0.25
+///     tags = ["dopant"]
+/// "#).unwrap();
+/// let alloy = Alloy::try_from_spec(&spec).unwrap();
+/// let lattice = Lattice::bcc(1.0)
+///     .expand_all(4)
+///     .alloy_sites_exact_where(&spec.selector(), alloy, &mut rand::rng());
+///
+/// let count = |kind| lattice.sites().iter().filter(|s| s.kind() == kind).count();
+/// assert_eq!(count("Fe"), 96);
+/// assert_eq!(count("Ni"), 32);
+/// ```
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlloySpec {
+    sources: Vec<String>,
+    targets: Vec<TargetSpec>,
+}
+
+impl AlloySpec {
+    /// Create a specification replacing the `sources` kinds with `targets`
+    pub fn try_new(sources: Vec<&str>, targets: Vec<TargetSpec>) -> Result<Self> {
+        let spec = AlloySpec {
+            sources: sources.iter().map(|source| source.to_string()).collect(),
+            targets,
+        };
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Reads a specification from JSON
+    pub fn from_str_json(source: &str) -> Result<Self> {
+        let spec: AlloySpec = serde_json::from_str(source)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// Reads a specification from TOML
+    pub fn from_str_toml(source: &str) -> Result<Self> {
+        let spec: AlloySpec = toml::from_str(source)?;
+        spec.validate()?;
+        Ok(spec)
+    }
+
+    /// The kinds of the sites to substitute
+    pub fn sources(&self) -> Vec<&str> {
+        self.sources.iter().map(|source| source.as_str()).collect()
+    }
+
+    /// The kinds to substitute the sites with
+    pub fn targets(&self) -> &[TargetSpec] {
+        &self.targets
+    }
+
+    /// Chooses the sites of any of the source kinds
+    pub fn selector(&self) -> Selector {
+        Selector::Or(
+            self.sources
+                .iter()
+                .map(|source| Selector::Kind(source.clone()))
+                .collect(),
+        )
+    }
+
+    fn validate(&self) -> Result<()> {
+        let invalid = |message: String| Err(VegasLatticeError::InvalidSpec(message));
+        if self.sources.is_empty() {
+            return invalid("no source kinds".to_string());
+        }
+        if self.targets.is_empty() {
+            return invalid("no target kinds".to_string());
+        }
+        for (index, target) in self.targets.iter().enumerate() {
+            if !target.fraction.is_finite() || target.fraction < 0.0 {
+                return invalid(format!(
+                    "invalid fraction {} for {}",
+                    target.fraction, target.kind
+                ));
+            }
+            if self.targets[..index].iter().any(|t| t.kind == target.kind) {
+                return invalid(format!("repeated target kind {}", target.kind));
+            }
+        }
+        if self.targets.iter().all(|target| target.fraction == 0.0) {
+            return invalid("fractions add up to zero".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl FromStr for AlloySpec {
+    type Err = VegasLatticeError;
+    fn from_str(source: &str) -> Result<Self> {
+        AlloySpec::from_str_json(source)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use super::Alloy;
+    use super::{Alloy, AlloySpec, TargetSpec};
+    use crate::error::VegasLatticeError;
 
     #[test]
     fn test_alloy_can_be_created_from_kinds_and_ratios() {
@@ -92,10 +321,135 @@ mod tests {
         assert!(kind == "A" || kind == "B");
     }
 
+    #[test]
+    fn test_alloy_counts_are_exact() {
+        let alloy = Alloy::try_new(vec!["A", "B"], vec![1, 1]).unwrap();
+        assert_eq!(alloy.counts(10), vec![5, 5]);
+        assert_eq!(alloy.counts(11), vec![6, 5]);
+        let alloy = Alloy::try_new(vec!["A", "B", "C"], vec![1, 2, 7]).unwrap();
+        assert_eq!(alloy.counts(100), vec![10, 20, 70]);
+        assert_eq!(alloy.counts(7), vec![1, 1, 5]);
+        assert_eq!(alloy.counts(0), vec![0, 0, 0]);
+    }
+
+    #[test]
+    fn test_alloy_counts_skip_kinds_without_ratio() {
+        let alloy = Alloy::try_new(vec!["A", "B", "C"], vec![0, 1, 1]).unwrap();
+        assert_eq!(alloy.counts(3), vec![0, 2, 1]);
+    }
+
     #[test]
     fn test_alloy_doesnt_pick_an_atom_with_no_ratios() {
         let targets = vec![];
         let result = Alloy::try_from_targets(targets);
         assert!(result.is_err());
     }
+
+    #[test]
+    fn test_alloy_counts_follow_fractions() {
+        let alloy = Alloy::try_from_fractions(vec!["A", "B"], vec![0.3, 0.7]).unwrap();
+        assert_eq!(alloy.counts(10), vec![3, 7]);
+        assert_eq!(alloy.counts(3), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_alloy_spec_can_be_read_from_json() {
+        let spec: AlloySpec = r#"{
+            "sources": ["A"],
+            "targets": [
+                {"kind": "Fe", "fraction": 0.5},
+                {"kind": "Ni", "fraction": 0.5, "tags": ["dopant"]}
+            ]
+        }"#
+        .parse()
+        .unwrap();
+        assert_eq!(spec.sources(), vec!["A"]);
+        assert_eq!(
+            spec.targets(),
+            &[
+                TargetSpec::new("Fe", 0.5),
+                TargetSpec::new("Ni", 0.5).with_tags(vec!["dopant"])
+            ]
+        );
+    }
+
+    #[test]
+    fn test_alloy_spec_can_be_read_from_toml() {
+        let spec = AlloySpec::from_str_toml(
+            r#"
+            sources = ["A", "B"]
+            targets = [{ kind = "Fe", fraction = 2 }, { kind = "Ni", fraction = 0.5 }]
+            "#,
+        )
+        .unwrap();
+        assert_eq!(spec.sources(), vec!["A", "B"]);
+        assert_eq!(
+            spec.targets(),
+            &[TargetSpec::new("Fe", 2.0), TargetSpec::new("Ni", 0.5)]
+        );
+    }
+
+    #[test]
+    fn test_alloy_spec_rejects_malformed_specs() {
+        let invalid = |sources: Vec<&str>, targets: Vec<TargetSpec>| {
+            matches!(
+                AlloySpec::try_new(sources, targets),
+                Err(VegasLatticeError::InvalidSpec(_))
+            )
+        };
+        assert!(invalid(vec![], vec![TargetSpec::new("Fe", 1.0)]));
+        assert!(invalid(vec!["A"], vec![]));
+        assert!(invalid(vec!["A"], vec![TargetSpec::new("Fe", -1.0)]));
+        assert!(invalid(vec!["A"], vec![TargetSpec::new("Fe", f64::NAN)]));
+        assert!(invalid(vec!["A"], vec![TargetSpec::new("Fe", 0.0)]));
+        assert!(invalid(
+            vec!["A"],
+            vec![TargetSpec::new("Fe", 1.0), TargetSpec::new("Fe", 2.0)]
+        ));
+        assert!(matches!(
+            AlloySpec::from_str_toml("sources = ["),
+            Err(VegasLatticeError::TomlError(_))
+        ));
+        assert!(matches!(
+            AlloySpec::from_str_json(r#"{"sources": ["A"]}"#),
+            Err(VegasLatticeError::SerializationError(_))
+        ));
+    }
+
+    #[test]
+    fn test_alloy_spec_rejects_unknown_fields() {
+        // Misspelled fields would otherwise fall back to their defaults
+        assert!(matches!(
+            AlloySpec::from_str_json(
+                r#"{"sources": ["A"], "targets": [{"kind": "Ni", "fraction": 1, "tag": ["x"]}]}"#
+            ),
+            Err(VegasLatticeError::SerializationError(_))
+        ));
+        assert!(matches!(
+            AlloySpec::from_str_toml(
+                r#"
+                source = ["B"]
+                sources = ["A"]
+                targets = [{ kind = "Ni", fraction = 1 }]
+                "#
+            ),
+            Err(VegasLatticeError::TomlError(_))
+        ));
+    }
+
+    #[test]
+    fn test_alloy_from_spec_tags_substituted_sites() {
+        let spec = AlloySpec::try_new(
+            vec!["A"],
+            vec![
+                TargetSpec::new("Fe", 1.0),
+                TargetSpec::new("Ni", 1.0).with_tags(vec!["dopant"]),
+            ],
+        )
+        .unwrap();
+        let alloy = Alloy::try_from_spec(&spec).unwrap();
+        assert_eq!(alloy.kinds(), ["Fe".to_string(), "Ni".to_string()]);
+        assert!(alloy.tags(0).is_empty());
+        assert_eq!(alloy.tags(1), ["dopant".to_string()]);
+    }
 }
@@ -43,6 +43,40 @@ pub struct Edge {
     target: usize,
     delta: (i32, i32, i32),
     tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    exchange: Option<Exchange>,
+}
+
+/// Represents the interaction strength of an edge.
+///
+/// In the lattice format it is either a number, for isotropic exchange, or a
+/// 3x3 matrix written row by row, for tensorial exchange.
+///
+/// # Examples
+///
+/// ```rust
+/// use vegas_lattice::{Edge, Exchange};
+///
+/// let edge = Edge::new(0, 0, (1, 0, 0)).with_exchange(Exchange::Isotropic(-1.5));
+/// assert_eq!(edge.exchange(), Some(Exchange::Isotropic(-1.5)));
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Exchange {
+    /// A single exchange constant
+    Isotropic(f64),
+    /// A full exchange tensor, row by row
+    Tensor([(f64, f64, f64); 3]),
+}
+
+impl Exchange {
+    /// Returns the exchange as a tensor, isotropic exchange becomes diagonal
+    pub fn tensor(&self) -> [(f64, f64, f64); 3] {
+        match *self {
+            Exchange::Isotropic(j) => [(j, 0.0, 0.0), (0.0, j, 0.0), (0.0, 0.0, j)],
+            Exchange::Tensor(tensor) => tensor,
+        }
+    }
 }
 
 impl FromStr for Edge {
@@ -68,6 +102,7 @@ impl Edge {
             target,
             delta,
             tags: None,
+            exchange: None,
         }
     }
 
@@ -86,6 +121,17 @@ impl Edge {
         self.delta
     }
 
+    /// Returns the `exchange` of the edge, if any
+    pub fn exchange(&self) -> Option<Exchange> {
+        self.exchange
+    }
+
+    /// Changes the exchange of the edge
+    pub fn with_exchange(mut self, exchange: Exchange) -> Self {
+        self.exchange = Some(exchange);
+        self
+    }
+
     /// Chagges the tags of the edge
     pub fn with_tags(mut self, tags: Vec<&str>) -> Self {
         self.tags = Some(tags.iter().map(|s| s.to_string()).collect());
@@ -162,7 +208,7 @@ impl Edge {
 
 #[cfg(test)]
 mod test {
-    use super::Edge;
+    use super::{Edge, Exchange};
 
     #[test]
     fn edge_can_be_created() {
@@ -234,4 +280,49 @@ mod test {
             Some(vec!["core".to_string(), "inner".to_string()])
         );
     }
+
+    #[test]
+    fn edge_will_take_optional_isotropic_exchange() {
+        let data = r#"
+            {"source": 0, "target": 0, "delta": [0, 0, 1], "exchange": 2.5}
+        "#;
+        let edge: Edge = data.parse().unwrap();
+        assert_eq!(edge.exchange, Some(Exchange::Isotropic(2.5)));
+    }
+
+    #[test]
+    fn edge_will_take_optional_tensorial_exchange() {
+        let data = r#"
+            {
+                "source": 0,
+                "target": 0,
+                "delta": [0, 0, 1],
+                "exchange": [[1, 0, 0], [0, 1, 0], [0, 0, 2]]
+            }
+        "#;
+        let edge: Edge = data.parse().unwrap();
+        assert_eq!(
+            edge.exchange,
+            Some(Exchange::Tensor([
+                (1.0, 0.0, 0.0),
+                (0.0, 1.0, 0.0),
+                (0.0, 0.0, 2.0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn exchange_is_kept_when_moving_and_reindexing() {
+        let edge = Edge::new(0, 1, (0, 0, 1))
+            .with_exchange(Exchange::Isotropic(1.5))
+            .move_z(1, 2, 2)
+            .reindex(&[1, 0, 2, 3]);
+        assert_eq!(edge.exchange, Some(Exchange::Isotropic(1.5)));
+    }
+
+    #[test]
+    fn isotropic_exchange_is_a_diagonal_tensor() {
+        let tensor = Exchange::Isotropic(2.0).tensor();
+        assert_eq!(tensor, [(2.0, 0.0, 0.0), (0.0, 2.0, 0.0), (0.0, 0.0, 2.0)]);
+    }
 }
@@ -18,10 +18,26 @@ pub enum VegasLatticeError {
     InconsistentEdges,
     #[error("negative size")]
     NegativeSize,
+    #[error("degenerate lattice vectors")]
+    DegenerateVectors,
+    #[error("the cell has no volume")]
+    ZeroVolume,
     #[error("inconsistent weights")]
     InconsistentWeights(#[from] WeightedError),
     #[error("invalid ratios")]
     InvalidRatios,
+    #[error("invalid gradient")]
+    InvalidGradient,
+    #[error("invalid alloy spec: {0}")]
+    InvalidSpec(String),
+    #[error("TOML error: {0}")]
+    TomlError(#[from] toml::de::Error),
+    #[error("parse error at line {line}: {message}")]
+    ParseError { line: usize, message: String },
+    #[error("the format can not hold a lattice without sites")]
+    EmptyLattice,
+    #[error("invalid binary lattice: {0}")]
+    BinaryFormat(String),
 }
 
 /// Result type for the vegas lattice crate
@@ -0,0 +1,272 @@
+//! Alloys whose composition changes with the position of the sites
+
+use crate::error::{Result, VegasLatticeError};
+use crate::util::{dot, norm, sub};
+use rand::{Rng, RngExt};
+
+/// Where the composition of a graded alloy changes, maps a position to a
+/// parameter between zero, the start composition, and one, the end one.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Gradient {
+    /// Along the line going from `from` to `to`, positions are projected on
+    /// the line so the composition is constant on the planes normal to it
+    Planar {
+        from: (f64, f64, f64),
+        to: (f64, f64, f64),
+    },
+    /// Away from `center`, reaching the end composition at `radius`
+    Radial {
+        center: (f64, f64, f64),
+        radius: f64,
+    },
+}
+
+impl Gradient {
+    /// Parameter of the given position, clamped to the `[0, 1]` range
+    pub fn parameter(&self, position: (f64, f64, f64)) -> f64 {
+        let t = match *self {
+            Gradient::Planar { from, to } => {
+                let direction = sub(to, from);
+                dot(sub(position, from), direction) / dot(direction, direction)
+            }
+            Gradient::Radial { center, radius } => norm(sub(position, center)) / radius,
+        };
+        t.clamp(0.0, 1.0)
+    }
+
+    fn is_valid(&self) -> bool {
+        match *self {
+            Gradient::Planar { from, to } => norm(sub(to, from)) > 0.0,
+            Gradient::Radial { radius, .. } => radius > 0.0,
+        }
+    }
+}
+
+/// How fast the composition goes from start to end, maps the parameter of
+/// the gradient to the fraction of the end composition in the mix.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub enum Profile {
+    /// The fraction is the parameter itself
+    #[default]
+    Linear,
+    /// Piecewise linear interpolation of `(parameter, fraction)` points,
+    /// sorted by parameter, the fractions are kept at the ends
+    Custom(Vec<(f64, f64)>),
+}
+
+impl Profile {
+    /// Fraction of the end composition at the given parameter
+    pub fn fraction(&self, t: f64) -> f64 {
+        let fraction = match self {
+            Profile::Linear => t,
+            Profile::Custom(points) => {
+                let after = points.partition_point(|&(x, _)| x <= t);
+                match (after, points.get(after)) {
+                    (0, _) => points[0].1,
+                    (_, None) => points[after - 1].1,
+                    (_, Some(&(x1, y1))) => {
+                        let (x0, y0) = points[after - 1];
+                        y0 + (y1 - y0) * (t - x0) / (x1 - x0)
+                    }
+                }
+            }
+        };
+        fraction.clamp(0.0, 1.0)
+    }
+
+    fn is_valid(&self) -> bool {
+        match self {
+            Profile::Linear => true,
+            Profile::Custom(points) => {
+                !points.is_empty()
+                    && points.iter().all(|&(x, y)| x.is_finite() && y.is_finite())
+                    && points.windows(2).all(|pair| pair[0].0 <= pair[1].0)
+            }
+        }
+    }
+}
+
+/// An alloy whose ratios depend on the position of the site, they go from
+/// the start ratios to the end ratios following a gradient and a profile.
+///
+/// # Example
+///
+/// ```rust
+/// use vegas_lattice::{GradedAlloy, Gradient};
+///
+/// let gradient = Gradient::Planar { from: (0.0, 0.0, 0.0), to: (0.0, 0.0, 10.0) };
+/// let alloy = GradedAlloy::try_new(vec!["Fe", "Ni"], vec![1.0, 0.0], vec![0.0, 1.0], gradient)
+///     .unwrap();
+///
+/// assert_eq!(alloy.pick((0.0, 0.0, 0.0), &mut rand::rng()), "Fe");
+/// assert_eq!(alloy.pick((0.0, 0.0, 10.0), &mut rand::rng()), "Ni");
+/// assert_eq!(alloy.ratios((0.0, 0.0, 2.5)), vec![0.75, 0.25]);
+/// ```
+#[derive(Debug, Clone)]
+pub struct GradedAlloy {
+    kinds: Vec<String>,
+    start: Vec<f64>,
+    end: Vec<f64>,
+    gradient: Gradient,
+    profile: Profile,
+}
+
+impl GradedAlloy {
+    /// Create a graded alloy with a linear profile, the ratios of each end
+    /// must be non negative and add up to a positive amount
+    pub fn try_new(
+        kinds: Vec<&str>,
+        start: Vec<f64>,
+        end: Vec<f64>,
+        gradient: Gradient,
+    ) -> Result<Self> {
+        let valid = |ratios: &[f64]| {
+            ratios.len() == kinds.len()
+                && ratios.iter().all(|&r| r.is_finite() && r >= 0.0)
+                && ratios.iter().sum::<f64>() > 0.0
+        };
+        if !valid(&start) || !valid(&end) {
+            return Err(VegasLatticeError::InvalidRatios);
+        }
+        if !gradient.is_valid() {
+            return Err(VegasLatticeError::InvalidGradient);
+        }
+        Ok(Self {
+            kinds: kinds.into_iter().map(String::from).collect(),
+            start,
+            end,
+            gradient,
+            profile: Profile::Linear,
+        })
+    }
+
+    /// Changes the profile of the alloy
+    pub fn try_with_profile(mut self, profile: Profile) -> Result<Self> {
+        if !profile.is_valid() {
+            return Err(VegasLatticeError::InvalidGradient);
+        }
+        self.profile = profile;
+        Ok(self)
+    }
+
+    /// Ratios of the kinds at the given position, normalized to add up to one
+    pub fn ratios(&self, position: (f64, f64, f64)) -> Vec<f64> {
+        let s = self.profile.fraction(self.gradient.parameter(position));
+        let start: f64 = self.start.iter().sum();
+        let end: f64 = self.end.iter().sum();
+        self.start
+            .iter()
+            .zip(self.end.iter())
+            .map(|(a, b)| (1.0 - s) * a / start + s * b / end)
+            .collect()
+    }
+
+    /// Picks a kind of atom for a site at the given position
+    pub fn pick<R: Rng>(&self, position: (f64, f64, f64), rng: &mut R) -> &str {
+        &self.kinds[self.pick_index(position, rng)]
+    }
+
+    /// Picks a kind of atom for a site at the given position, as an index
+    /// into the kinds of the alloy
+    pub(crate) fn pick_index<R: Rng>(&self, position: (f64, f64, f64), rng: &mut R) -> usize {
+        let ratios = self.ratios(position);
+        let mut left = rng.random::<f64>() * ratios.iter().sum::<f64>();
+        for (index, ratio) in ratios.iter().enumerate() {
+            if left < *ratio {
+                return index;
+            }
+            left -= ratio;
+        }
+        // Rounding may leave a tiny amount, give it to the last kind present
+        ratios.iter().rposition(|&ratio| ratio > 0.0).unwrap_or(0)
+    }
+
+    /// The kinds of atoms of the alloy
+    pub(crate) fn kinds(&self) -> &[String] {
+        &self.kinds
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{GradedAlloy, Gradient, Profile};
+
+    fn along_z() -> Gradient {
+        Gradient::Planar {
+            from: (0.0, 0.0, 0.0),
+            to: (0.0, 0.0, 4.0),
+        }
+    }
+
+    #[test]
+    fn planar_gradient_projects_on_the_line() {
+        let gradient = along_z();
+        assert_eq!(gradient.parameter((3.0, -1.0, 1.0)), 0.25);
+        assert_eq!(gradient.parameter((0.0, 0.0, -2.0)), 0.0);
+        assert_eq!(gradient.parameter((0.0, 0.0, 8.0)), 1.0);
+    }
+
+    #[test]
+    fn radial_gradient_grows_with_the_distance() {
+        let gradient = Gradient::Radial {
+            center: (1.0, 1.0, 1.0),
+            radius: 2.0,
+        };
+        assert_eq!(gradient.parameter((1.0, 1.0, 1.0)), 0.0);
+        assert_eq!(gradient.parameter((1.0, 2.0, 1.0)), 0.5);
+        assert_eq!(gradient.parameter((5.0, 1.0, 1.0)), 1.0);
+    }
+
+    #[test]
+    fn custom_profile_interpolates_between_points() {
+        let profile = Profile::Custom(vec![(0.2, 0.0), (0.6, 1.0), (1.0, 0.5)]);
+        assert_eq!(profile.fraction(0.0), 0.0);
+        assert!((profile.fraction(0.4) - 0.5).abs() < 1e-12);
+        assert_eq!(profile.fraction(0.6), 1.0);
+        assert!((profile.fraction(0.8) - 0.75).abs() < 1e-12);
+        assert_eq!(profile.fraction(1.0), 0.5);
+    }
+
+    #[test]
+    fn ratios_follow_the_profile() {
+        let alloy = GradedAlloy::try_new(vec!["A", "B"], vec![3.0, 1.0], vec![0.0, 2.0], along_z())
+            .unwrap()
+            .try_with_profile(Profile::Custom(vec![(0.0, 0.0), (0.5, 1.0)]))
+            .unwrap();
+        assert_eq!(alloy.ratios((0.0, 0.0, 0.0)), vec![0.75, 0.25]);
+        assert_eq!(alloy.ratios((0.0, 0.0, 1.0)), vec![0.375, 0.625]);
+        assert_eq!(alloy.ratios((0.0, 0.0, 3.0)), vec![0.0, 1.0]);
+    }
+
+    #[test]
+    fn picks_only_kinds_present_at_the_position() {
+        let alloy = GradedAlloy::try_new(vec!["A", "B"], vec![1.0, 0.0], vec![0.0, 1.0], along_z())
+            .unwrap();
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            assert_eq!(alloy.pick((0.0, 0.0, 0.0), &mut rng), "A");
+            assert_eq!(alloy.pick((0.0, 0.0, 4.0), &mut rng), "B");
+        }
+    }
+
+    #[test]
+    fn invalid_alloys_are_rejected() {
+        assert!(GradedAlloy::try_new(vec!["A"], vec![1.0, 0.0], vec![1.0], along_z()).is_err());
+        assert!(GradedAlloy::try_new(vec!["A"], vec![0.0], vec![1.0], along_z()).is_err());
+        assert!(GradedAlloy::try_new(vec!["A"], vec![-1.0], vec![1.0], along_z()).is_err());
+        let point = Gradient::Planar {
+            from: (1.0, 1.0, 1.0),
+            to: (1.0, 1.0, 1.0),
+        };
+        assert!(GradedAlloy::try_new(vec!["A"], vec![1.0], vec![1.0], point).is_err());
+        let alloy = GradedAlloy::try_new(vec!["A"], vec![1.0], vec![1.0], along_z()).unwrap();
+        assert!(
+            alloy
+                .clone()
+                .try_with_profile(Profile::Custom(vec![]))
+                .is_err()
+        );
+        let unsorted = Profile::Custom(vec![(1.0, 0.0), (0.0, 1.0)]);
+        assert!(alloy.try_with_profile(unsorted).is_err());
+    }
+}
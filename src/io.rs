@@ -1,10 +1,44 @@
-//! Defines the `to_writer_lattice` function for serializing a type to a writer
+//! Defines the `to_writer_lattice` function for serializing a type to a writer,
+//! as well as readers and writers for other file formats.
 
-use crate::error::Result;
+use crate::{Lattice, error::Result};
 use serde::ser;
 use serde_json::ser::{Formatter, Serializer};
 use std::io;
 
+mod binary;
+mod cif;
+mod dot;
+mod graphml;
+mod lammps;
+mod parse;
+mod poscar;
+mod vampire;
+mod vtk;
+mod xyz;
+
+pub use binary::{
+    MAGIC, from_reader_binary, from_slice_binary, is_binary, to_vec_binary, to_writer_binary,
+    to_writer_binary_supercell,
+};
+pub use cif::{from_reader_cif, from_str_cif};
+pub use dot::{to_string_dot, to_writer_dot};
+pub use graphml::{to_string_graphml, to_writer_graphml};
+pub use lammps::{to_string_lammps, to_writer_lammps};
+pub use poscar::{from_reader_poscar, from_str_poscar, to_string_poscar, to_writer_poscar};
+pub use vampire::{from_reader_vampire, from_str_vampire, to_string_vampire, to_writer_vampire};
+pub use vtk::{to_string_vtk, to_writer_vtk};
+pub use xyz::{
+    from_reader_xyz, from_str_xyz, to_string_xyz, to_writer_xyz, to_writer_xyz_supercell,
+};
+
+/// Turns the output of a writer into a string, the writers only emit valid
+/// UTF-8 so a failure here is a bug
+#[inline]
+pub(crate) fn into_string(buffer: Vec<u8>) -> String {
+    String::from_utf8(buffer).expect("writers emit valid UTF-8")
+}
+
 /// A formatter for serializing to a writer with a lattice style
 ///
 /// Essentially formats the json as a lattice, with a max indent of 2.
@@ -186,11 +220,17 @@ where
     T: ?Sized + ser::Serialize,
 {
     let vec = to_vec_lattice(value)?;
-    let string = unsafe {
-        // We do not emit invalid UTF-8.
-        String::from_utf8_unchecked(vec)
-    };
-    Ok(string)
+    Ok(into_string(vec))
+}
+
+/// Deserializes a lattice from a reader with its JSON representation, it is
+/// validated just like when parsed from a string
+pub fn from_reader_lattice<R>(reader: R) -> Result<Lattice>
+where
+    R: io::Read,
+{
+    let lattice: Lattice = serde_json::from_reader(reader)?;
+    lattice.validate()
 }
 
 fn indent<W>(wr: &mut W, n: usize, s: &[u8]) -> io::Result<()>
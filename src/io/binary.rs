@@ -0,0 +1,508 @@
+//! Compact binary lattice files
+//!
+//! The file starts with the magic bytes `VGLT` and a version number, then
+//! comes the size and lattice vectors, a table with every kind and tag used in
+//! the lattice, and the sites and edges, which refer to the table by index.
+//! Since version 2 the metadata of the lattice follows, as pairs of strings,
+//! and since version 3 the optional index in the unit cell of every site.
+//! Every number is little endian, strings and arrays are prefixed with their
+//! length and optional fields with a flag byte.
+
+use crate::{
+    Edge, Exchange, Lattice, Site, SiteRef, Supercell, Tagged,
+    error::{Result, VegasLatticeError},
+};
+use std::{
+    borrow::Borrow,
+    collections::HashMap,
+    io::{Read, Write},
+};
+
+/// Magic bytes at the start of every binary lattice
+pub const MAGIC: &[u8; 4] = b"VGLT";
+
+/// Version of the binary format written by this crate, files of older
+/// versions are still read
+const VERSION: u32 = 3;
+
+/// Returns whether some data looks like a binary lattice
+pub fn is_binary(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+fn invalid(message: impl Into<String>) -> VegasLatticeError {
+    VegasLatticeError::BinaryFormat(message.into())
+}
+
+/// Table of the strings used in a lattice, kinds and tags are stored only
+/// once and referred to by index
+#[derive(Default)]
+struct Strings<'a> {
+    strings: Vec<&'a str>,
+    indices: HashMap<&'a str, u32>,
+}
+
+impl<'a> Strings<'a> {
+    fn insert(&mut self, string: &'a str) {
+        if !self.indices.contains_key(string) {
+            self.indices.insert(string, self.strings.len() as u32);
+            self.strings.push(string);
+        }
+    }
+
+    fn index(&self, string: &str) -> u32 {
+        self.indices[string]
+    }
+}
+
+fn write_u8<W: Write>(writer: &mut W, value: u8) -> Result<()> {
+    writer.write_all(&[value])?;
+    Ok(())
+}
+
+fn write_u32<W: Write>(writer: &mut W, value: u32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_u64<W: Write>(writer: &mut W, value: u64) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_i32<W: Write>(writer: &mut W, value: i32) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_f64<W: Write>(writer: &mut W, value: f64) -> Result<()> {
+    writer.write_all(&value.to_le_bytes())?;
+    Ok(())
+}
+
+fn write_triplet<W: Write>(writer: &mut W, (x, y, z): (f64, f64, f64)) -> Result<()> {
+    write_f64(writer, x)?;
+    write_f64(writer, y)?;
+    write_f64(writer, z)
+}
+
+fn write_string<W: Write>(writer: &mut W, string: &str) -> Result<()> {
+    write_u32(writer, string.len() as u32)?;
+    writer.write_all(string.as_bytes())?;
+    Ok(())
+}
+
+fn write_tags<W: Write>(writer: &mut W, strings: &Strings, tags: Option<Vec<&str>>) -> Result<()> {
+    match tags {
+        None => write_u8(writer, 0),
+        Some(tags) => {
+            write_u8(writer, 1)?;
+            write_u32(writer, tags.len() as u32)?;
+            for tag in tags {
+                write_u32(writer, strings.index(tag))?;
+            }
+            Ok(())
+        }
+    }
+}
+
+fn read_array<R: Read, const N: usize>(reader: &mut R) -> Result<[u8; N]> {
+    let mut buffer = [0; N];
+    reader.read_exact(&mut buffer)?;
+    Ok(buffer)
+}
+
+fn read_u8<R: Read>(reader: &mut R) -> Result<u8> {
+    Ok(u8::from_le_bytes(read_array(reader)?))
+}
+
+fn read_u32<R: Read>(reader: &mut R) -> Result<u32> {
+    Ok(u32::from_le_bytes(read_array(reader)?))
+}
+
+fn read_u64<R: Read>(reader: &mut R) -> Result<u64> {
+    Ok(u64::from_le_bytes(read_array(reader)?))
+}
+
+fn read_i32<R: Read>(reader: &mut R) -> Result<i32> {
+    Ok(i32::from_le_bytes(read_array(reader)?))
+}
+
+fn read_f64<R: Read>(reader: &mut R) -> Result<f64> {
+    Ok(f64::from_le_bytes(read_array(reader)?))
+}
+
+fn read_triplet<R: Read>(reader: &mut R) -> Result<(f64, f64, f64)> {
+    Ok((read_f64(reader)?, read_f64(reader)?, read_f64(reader)?))
+}
+
+fn read_owned_string<R: Read>(reader: &mut R) -> Result<String> {
+    let length = read_u32(reader)?;
+    let mut bytes = Vec::with_capacity(length.min(MAX_CAPACITY as u32) as usize);
+    reader.take(length as u64).read_to_end(&mut bytes)?;
+    if bytes.len() != length as usize {
+        return Err(invalid("truncated string"));
+    }
+    String::from_utf8(bytes).map_err(|_| invalid("invalid UTF-8 string"))
+}
+
+fn read_string(strings: &[String], index: u32) -> Result<&str> {
+    strings
+        .get(index as usize)
+        .map(|string| string.as_str())
+        .ok_or_else(|| invalid(format!("unknown string {}", index)))
+}
+
+fn read_tags<'a, R: Read>(reader: &mut R, strings: &'a [String]) -> Result<Option<Vec<&'a str>>> {
+    match read_u8(reader)? {
+        0 => Ok(None),
+        1 => {
+            let count = read_u32(reader)?;
+            let tags = (0..count)
+                .map(|_| read_string(strings, read_u32(reader)?))
+                .collect::<Result<_>>()?;
+            Ok(Some(tags))
+        }
+        flag => Err(invalid(format!("unknown tags flag {}", flag))),
+    }
+}
+
+/// Preallocates room for at most this many elements, so a corrupted count
+/// can not exhaust the memory before reading fails
+const MAX_CAPACITY: u64 = 1 << 20;
+
+/// Writes the whole file, the strings are taken from `lattice` while the
+/// sites and edges come from the iterators, so it can be used for supercells.
+/// The indices of the sites in the unit cell go at the end, so `bases` must
+/// follow the order of `sites`.
+fn write_binary<'s, W, S, E, B>(
+    writer: &mut W,
+    lattice: &Lattice,
+    size: (f64, f64, f64),
+    sites: (usize, S),
+    edges: (usize, E),
+    bases: B,
+) -> Result<()>
+where
+    W: Write,
+    S: Iterator<Item = SiteRef<'s>>,
+    E: Iterator<Item: Borrow<Edge>>,
+    B: Iterator<Item = Option<usize>>,
+{
+    let mut strings = Strings::default();
+    for site in lattice.sites() {
+        strings.insert(site.kind());
+        site.tags()
+            .into_iter()
+            .flatten()
+            .for_each(|tag| strings.insert(tag));
+    }
+    for edge in lattice.edges() {
+        edge.tags()
+            .into_iter()
+            .flatten()
+            .for_each(|tag| strings.insert(tag));
+    }
+
+    writer.write_all(MAGIC)?;
+    write_u32(writer, VERSION)?;
+    write_triplet(writer, size)?;
+    for vector in lattice.vectors() {
+        write_triplet(writer, vector)?;
+    }
+
+    write_u32(writer, strings.strings.len() as u32)?;
+    for string in strings.strings.iter() {
+        write_string(writer, string)?;
+    }
+
+    let (count, sites) = sites;
+    write_u64(writer, count as u64)?;
+    for site in sites {
+        write_u32(writer, strings.index(site.kind()))?;
+        write_triplet(writer, site.position())?;
+        write_tags(writer, &strings, site.tags())?;
+    }
+
+    let (count, edges) = edges;
+    write_u64(writer, count as u64)?;
+    for edge in edges {
+        let edge = edge.borrow();
+        let (dx, dy, dz) = edge.delta();
+        write_u64(writer, edge.source() as u64)?;
+        write_u64(writer, edge.target() as u64)?;
+        write_i32(writer, dx)?;
+        write_i32(writer, dy)?;
+        write_i32(writer, dz)?;
+        write_tags(writer, &strings, edge.tags())?;
+        match edge.exchange() {
+            None => write_u8(writer, 0)?,
+            Some(Exchange::Isotropic(j)) => {
+                write_u8(writer, 1)?;
+                write_f64(writer, j)?;
+            }
+            Some(Exchange::Tensor(tensor)) => {
+                write_u8(writer, 2)?;
+                for row in tensor {
+                    write_triplet(writer, row)?;
+                }
+            }
+        }
+    }
+
+    write_u32(writer, lattice.metadata().len() as u32)?;
+    for (key, value) in lattice.metadata() {
+        write_string(writer, key)?;
+        write_string(writer, value)?;
+    }
+
+    for basis in bases {
+        match basis {
+            None => write_u8(writer, 0)?,
+            Some(basis) => {
+                write_u8(writer, 1)?;
+                write_u32(writer, basis as u32)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Serializes a lattice to a writer in the binary format
+///
+/// Many small writes are issued, so the writer should be buffered.
+pub fn to_writer_binary<W>(mut writer: W, lattice: &Lattice) -> Result<()>
+where
+    W: Write,
+{
+    let sites = (lattice.sites().len(), lattice.sites().iter());
+    let edges = (lattice.edges().len(), lattice.edges().iter());
+    let bases = lattice.sites().iter().map(|site| site.basis());
+    write_binary(&mut writer, lattice, lattice.size(), sites, edges, bases)
+}
+
+/// Serializes a supercell to a writer in the binary format, the sites and
+/// edges are written as they are computed
+///
+/// Many small writes are issued, so the writer should be buffered.
+pub fn to_writer_binary_supercell<W>(mut writer: W, supercell: &Supercell) -> Result<()>
+where
+    W: Write,
+{
+    let sites = (supercell.site_count(), supercell.sites());
+    let edges = (supercell.edge_count(), supercell.edges());
+    let bases = supercell.sites().map(|site| site.basis());
+    write_binary(
+        &mut writer,
+        supercell.lattice(),
+        supercell.size(),
+        sites,
+        edges,
+        bases,
+    )
+}
+
+/// Serializes a lattice to a vector of bytes in the binary format
+pub fn to_vec_binary(lattice: &Lattice) -> Result<Vec<u8>> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_binary(&mut writer, lattice)?;
+    Ok(writer)
+}
+
+/// Deserializes a lattice from a reader in the binary format
+pub fn from_reader_binary<R>(mut reader: R) -> Result<Lattice>
+where
+    R: Read,
+{
+    let magic: [u8; 4] = read_array(&mut reader)?;
+    if &magic != MAGIC {
+        return Err(invalid("missing magic bytes"));
+    }
+    let version = read_u32(&mut reader)?;
+    if version == 0 || version > VERSION {
+        return Err(invalid(format!("unsupported version {}", version)));
+    }
+    let size = read_triplet(&mut reader)?;
+    let vectors = [
+        read_triplet(&mut reader)?,
+        read_triplet(&mut reader)?,
+        read_triplet(&mut reader)?,
+    ];
+
+    let count = read_u32(&mut reader)?;
+    let mut strings = Vec::with_capacity(count.min(MAX_CAPACITY as u32) as usize);
+    for _ in 0..count {
+        let string = read_owned_string(&mut reader)?;
+        strings.push(string);
+    }
+
+    let count = read_u64(&mut reader)?;
+    let mut sites = Vec::with_capacity(count.min(MAX_CAPACITY) as usize);
+    for _ in 0..count {
+        let index = read_u32(&mut reader)?;
+        read_string(&strings, index)?;
+        let position = read_triplet(&mut reader)?;
+        let mut site = Site::new(&strings[index as usize]).with_position(position);
+        if let Some(tags) = read_tags(&mut reader, &strings)? {
+            site = site.with_tags(tags);
+        }
+        sites.push(site);
+    }
+
+    let count = read_u64(&mut reader)?;
+    let mut edges = Vec::with_capacity(count.min(MAX_CAPACITY) as usize);
+    for _ in 0..count {
+        let source = read_u64(&mut reader)? as usize;
+        let target = read_u64(&mut reader)? as usize;
+        let delta = (
+            read_i32(&mut reader)?,
+            read_i32(&mut reader)?,
+            read_i32(&mut reader)?,
+        );
+        let mut edge = Edge::new(source, target, delta);
+        if let Some(tags) = read_tags(&mut reader, &strings)? {
+            edge = edge.with_tags(tags);
+        }
+        match read_u8(&mut reader)? {
+            0 => {}
+            1 => edge = edge.with_exchange(Exchange::Isotropic(read_f64(&mut reader)?)),
+            2 => {
+                let tensor = [
+                    read_triplet(&mut reader)?,
+                    read_triplet(&mut reader)?,
+                    read_triplet(&mut reader)?,
+                ];
+                edge = edge.with_exchange(Exchange::Tensor(tensor));
+            }
+            flag => return Err(invalid(format!("unknown exchange flag {}", flag))),
+        }
+        edges.push(edge);
+    }
+
+    let mut metadata = Vec::new();
+    if version >= 2 {
+        let count = read_u32(&mut reader)?;
+        for _ in 0..count {
+            let key = read_owned_string(&mut reader)?;
+            let value = read_owned_string(&mut reader)?;
+            metadata.push((key, value));
+        }
+    }
+    if version >= 3 {
+        sites = sites
+            .into_iter()
+            .map(|site| match read_u8(&mut reader)? {
+                0 => Ok(site),
+                1 => Ok(site.with_basis(read_u32(&mut reader)? as usize)),
+                flag => Err(invalid(format!("unknown basis flag {}", flag))),
+            })
+            .collect::<Result<_>>()?;
+    }
+
+    let mut lattice = Lattice::try_new(size)?
+        .try_with_vectors(vectors)?
+        .try_with_sites(sites)?
+        .try_with_edges(edges)?;
+    for (key, value) in metadata.iter() {
+        lattice = lattice.with_metadata(key, value);
+    }
+    Ok(lattice)
+}
+
+/// Deserializes a lattice from a slice of bytes in the binary format
+pub fn from_slice_binary(data: &[u8]) -> Result<Lattice> {
+    from_reader_binary(data)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_slice_binary, is_binary, to_vec_binary, to_writer_binary_supercell};
+    use crate::{Edge, Exchange, Lattice, Site, Tagged};
+
+    #[test]
+    fn binary_round_trip() {
+        let lattice = Lattice::hcp(1.0, 1.6)
+            .try_with_sites(vec![
+                Site::new("Fe").with_tags(vec!["core", "surface"]),
+                Site::new("Ni").with_position((0.0, 0.5, 0.8)).with_basis(3),
+            ])
+            .unwrap()
+            .try_with_edges(vec![
+                Edge::new(0, 1, (0, 0, 0)).with_tags(vec!["core"]),
+                Edge::new(1, 1, (0, -1, 2)).with_exchange(Exchange::Isotropic(-1.5)),
+                Edge::new(0, 0, (1, 0, 0)).with_exchange(Exchange::Tensor([
+                    (1.0, 2.0, 3.0),
+                    (4.0, 5.0, 6.0),
+                    (7.0, 8.0, 9.0),
+                ])),
+            ])
+            .unwrap()
+            .with_metadata("seeds", "alloy:42");
+        let data = to_vec_binary(&lattice).unwrap();
+        assert!(is_binary(&data));
+        let output = from_slice_binary(&data).unwrap();
+        assert_eq!(output.size(), lattice.size());
+        assert_eq!(output.vectors(), lattice.vectors());
+        assert_eq!(output.site(0).kind(), "Fe");
+        assert_eq!(output.site(0).tags(), Some(vec!["core", "surface"]));
+        assert_eq!(output.site(1).position(), (0.0, 0.5, 0.8));
+        assert_eq!(output.site(1).tags(), None);
+        assert_eq!(output.site(0).basis(), None);
+        assert_eq!(output.site(1).basis(), Some(3));
+        assert_eq!(output.edges()[0].tags(), Some(vec!["core"]));
+        assert_eq!(output.edges()[1].delta(), (0, -1, 2));
+        assert_eq!(output.edges()[1].exchange(), lattice.edges()[1].exchange());
+        assert_eq!(output.edges()[2].exchange(), lattice.edges()[2].exchange());
+        assert_eq!(output.metadata(), lattice.metadata());
+    }
+
+    #[test]
+    fn binary_reads_version_one() {
+        // Version 1 is version 3 without the metadata and the basis flags
+        let mut data = to_vec_binary(&Lattice::bcc(1.0)).unwrap();
+        data[4..8].copy_from_slice(&1u32.to_le_bytes());
+        data.truncate(data.len() - 2 - 4);
+        let lattice = from_slice_binary(&data).unwrap();
+        assert_eq!(lattice.sites().len(), 2);
+        assert!(lattice.metadata().is_empty());
+    }
+
+    #[test]
+    fn binary_reads_version_two() {
+        // Version 2 is version 3 without the basis flag of the two sites
+        let mut data = to_vec_binary(&Lattice::bcc(1.0).with_metadata("seeds", "1")).unwrap();
+        data[4..8].copy_from_slice(&2u32.to_le_bytes());
+        data.truncate(data.len() - 2);
+        let lattice = from_slice_binary(&data).unwrap();
+        assert_eq!(lattice.sites().len(), 2);
+        assert_eq!(lattice.site(1).basis(), None);
+        assert_eq!(lattice.metadata().len(), 1);
+    }
+
+    #[test]
+    fn binary_interns_kinds() {
+        let small = to_vec_binary(&Lattice::sc(1.0).expand_all(2)).unwrap();
+        let large = to_vec_binary(&Lattice::sc(1.0).expand_all(4)).unwrap();
+        // Sites cost their kind index, position, tags flag and index in the
+        // unit cell, while edges cost their ends, delta and the tags and
+        // exchange flags.
+        let sites = 64 - 8;
+        let edges = 3 * sites;
+        assert_eq!(large.len() - small.len(), sites * 34 + edges * 30);
+    }
+
+    #[test]
+    fn binary_supercell_matches_expansion() {
+        let lattice = Lattice::bcc(1.0).try_with_neighbor_shells(2).unwrap();
+        let mut data = Vec::new();
+        to_writer_binary_supercell(&mut data, &lattice.supercell(2, 3, 1)).unwrap();
+        assert_eq!(data, to_vec_binary(&lattice.expand(2, 3, 1)).unwrap());
+    }
+
+    #[test]
+    fn binary_fails_on_truncated_data() {
+        let data = to_vec_binary(&Lattice::bcc(1.0)).unwrap();
+        assert!(from_slice_binary(&data[..data.len() - 1]).is_err());
+        assert!(from_slice_binary(b"{\"size\": [1, 1, 1]}").is_err());
+    }
+}
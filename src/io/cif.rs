@@ -0,0 +1,515 @@
+//! Crystallographic Information Files (CIF)
+//!
+//! Only the first data block is read. The cell comes from the
+//! `_cell_length_*` and `_cell_angle_*` items, with the _a_ vector along the
+//! _x_ axis and the _b_ vector in the _xy_ plane. The asymmetric unit comes
+//! from the `_atom_site_fract_*` loop, using `_atom_site_type_symbol` (or
+//! `_atom_site_label` when missing) as site kinds, and it is expanded with the
+//! symmetry operations in `_symmetry_equiv_pos_as_xyz` or
+//! `_space_group_symop_operation_xyz`. Coincident positions are removed, and
+//! the resulting lattice has no edges.
+
+use super::parse::error;
+use crate::{Lattice, Site, error::Result, util::Vector};
+use std::{collections::HashMap, io::Read};
+
+/// Positions closer than this along every fractional coordinate are
+/// considered the same site, loose enough for coordinates rounded to three
+/// decimals such as `0.333` and `0.667`
+const TOLERANCE: f64 = 1e-2;
+
+/// Cosines smaller than this are rounding noise of right angles
+const RIGHT_ANGLE_COSINE: f64 = 1e-12;
+
+/// A token of a CIF file along with the line it was found in
+#[derive(Debug)]
+struct Token<'a> {
+    line: usize,
+    value: &'a str,
+    quoted: bool,
+}
+
+/// Splits a CIF file in tokens, taking care of comments, quoted strings and
+/// semicolon delimited text fields
+fn tokenize(source: &str) -> Vec<Token<'_>> {
+    let mut tokens = Vec::new();
+    let mut lines = source.lines().enumerate().map(|(i, line)| (i + 1, line));
+    while let Some((number, line)) = lines.next() {
+        if let Some(text) = line.strip_prefix(';') {
+            // Text fields are only used for descriptions, we keep the first line
+            tokens.push(Token {
+                line: number,
+                value: text.trim(),
+                quoted: true,
+            });
+            for (_, line) in lines.by_ref() {
+                if line.starts_with(';') {
+                    break;
+                }
+            }
+            continue;
+        }
+        let mut rest = line.trim_start();
+        while !rest.is_empty() {
+            if rest.starts_with('#') {
+                break;
+            }
+            let (value, quoted, remainder) = match rest.chars().next() {
+                Some(quote @ ('\'' | '"')) => {
+                    // A quote only closes a string when followed by a blank
+                    let body = &rest[1..];
+                    let end = body
+                        .char_indices()
+                        .find(|&(i, c)| {
+                            c == quote
+                                && body[i + 1..].chars().next().is_none_or(char::is_whitespace)
+                        })
+                        .map(|(i, _)| i)
+                        .unwrap_or(body.len());
+                    (&body[..end], true, body.get(end + 1..).unwrap_or(""))
+                }
+                _ => {
+                    let end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+                    (&rest[..end], false, &rest[end..])
+                }
+            };
+            tokens.push(Token {
+                line: number,
+                value,
+                quoted,
+            });
+            rest = remainder.trim_start();
+        }
+    }
+    tokens
+}
+
+/// The items and loops of a data block, tags are stored in lowercase
+#[derive(Debug, Default)]
+struct Block<'a> {
+    line: usize,
+    items: HashMap<String, &'a Token<'a>>,
+    loops: Vec<(Vec<String>, Vec<&'a Token<'a>>)>,
+}
+
+impl<'a> Block<'a> {
+    fn parse(tokens: &'a [Token<'a>]) -> Result<Self> {
+        let mut block = Block::default();
+        let mut started = false;
+        let mut index = 0;
+        while index < tokens.len() {
+            let token = &tokens[index];
+            let keyword = token.value.to_lowercase();
+            if token.quoted {
+                return Err(error(
+                    token.line,
+                    format!("unexpected value `{}`", token.value),
+                ));
+            } else if keyword.starts_with("data_") {
+                if started {
+                    break;
+                }
+                started = true;
+                block.line = token.line;
+                index += 1;
+            } else if keyword == "loop_" {
+                index += 1;
+                let mut tags = Vec::new();
+                while index < tokens.len() && is_tag(&tokens[index]) {
+                    tags.push(tokens[index].value.to_lowercase());
+                    index += 1;
+                }
+                let mut values = Vec::new();
+                while index < tokens.len() && !is_keyword(&tokens[index]) {
+                    values.push(&tokens[index]);
+                    index += 1;
+                }
+                if tags.is_empty() || values.len() % tags.len() != 0 {
+                    return Err(error(token.line, "malformed loop"));
+                }
+                block.loops.push((tags, values));
+            } else if is_tag(token) {
+                let value = tokens
+                    .get(index + 1)
+                    .filter(|value| !is_keyword(value))
+                    .ok_or_else(|| error(token.line, format!("missing value for `{}`", keyword)))?;
+                block.items.insert(keyword, value);
+                index += 2;
+            } else {
+                return Err(error(
+                    token.line,
+                    format!("unexpected value `{}`", token.value),
+                ));
+            }
+        }
+        Ok(block)
+    }
+
+    /// Returns the value of an item as a number, ignoring uncertainties
+    fn number(&self, tag: &str) -> Result<f64> {
+        let token = self
+            .items
+            .get(tag)
+            .ok_or_else(|| error(self.line, format!("missing `{}`", tag)))?;
+        number(token)
+    }
+
+    /// Returns a column of one of the loops, if any
+    fn column(&self, tag: &str) -> Option<Vec<&'a Token<'a>>> {
+        self.loops.iter().find_map(|(tags, values)| {
+            let index = tags.iter().position(|t| t == tag)?;
+            Some(
+                values
+                    .iter()
+                    .skip(index)
+                    .step_by(tags.len())
+                    .cloned()
+                    .collect(),
+            )
+        })
+    }
+}
+
+fn is_tag(token: &Token) -> bool {
+    !token.quoted && token.value.starts_with('_')
+}
+
+fn is_keyword(token: &Token) -> bool {
+    let value = token.value.to_lowercase();
+    is_tag(token) || (!token.quoted && (value == "loop_" || value.starts_with("data_")))
+}
+
+/// Parses a number like `5.4307(2)`, dropping the uncertainty
+fn number(token: &Token) -> Result<f64> {
+    let value = token.value.split('(').next().unwrap_or_default();
+    value
+        .parse()
+        .map_err(|_| error(token.line, format!("invalid number `{}`", token.value)))
+}
+
+/// An affine symmetry operation acting on fractional coordinates
+#[derive(Debug, Clone, Copy)]
+struct Operation {
+    rotation: [Vector; 3],
+    translation: Vector,
+}
+
+impl Operation {
+    /// Parses operations like `-x+1/2, y, z+1/2`
+    fn parse(token: &Token) -> Result<Self> {
+        let invalid = || error(token.line, format!("invalid operation `{}`", token.value));
+        let components: Vec<_> = token.value.split(',').collect();
+        if components.len() != 3 {
+            return Err(invalid());
+        }
+        let mut rotation = [(0.0, 0.0, 0.0); 3];
+        let mut translation = [0.0; 3];
+        for (i, component) in components.iter().enumerate() {
+            let (row, constant) = parse_component(component).ok_or_else(invalid)?;
+            rotation[i] = row;
+            translation[i] = constant;
+        }
+        Ok(Operation {
+            rotation,
+            translation: (translation[0], translation[1], translation[2]),
+        })
+    }
+
+    /// Applies the operation and brings the result back into the cell
+    fn apply(&self, (x, y, z): Vector) -> Vector {
+        let row = |(a, b, c): Vector, t: f64| (a * x + b * y + c * z + t).rem_euclid(1.0);
+        (
+            row(self.rotation[0], self.translation.0),
+            row(self.rotation[1], self.translation.1),
+            row(self.rotation[2], self.translation.2),
+        )
+    }
+}
+
+/// Parses a single component of a symmetry operation, like `-y+1/2`, into
+/// the coefficients of `x`, `y` and `z` and a constant
+fn parse_component(component: &str) -> Option<(Vector, f64)> {
+    let text: String = component
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .collect::<String>()
+        .to_lowercase();
+    let mut coefficients = [0.0; 3];
+    let mut constant = 0.0;
+    let mut chars = text.chars().peekable();
+    while chars.peek().is_some() {
+        let sign = match chars.peek() {
+            Some('-') => {
+                chars.next();
+                -1.0
+            }
+            Some('+') => {
+                chars.next();
+                1.0
+            }
+            _ => 1.0,
+        };
+        let mut number = String::new();
+        while let Some(&c) = chars.peek() {
+            if c.is_ascii_digit() || c == '.' || c == '/' {
+                number.push(c);
+                chars.next();
+            } else {
+                break;
+            }
+        }
+        let value = if number.is_empty() {
+            None
+        } else if let Some((numerator, denominator)) = number.split_once('/') {
+            Some(numerator.parse::<f64>().ok()? / denominator.parse::<f64>().ok()?)
+        } else {
+            Some(number.parse::<f64>().ok()?)
+        };
+        if chars.peek() == Some(&'*') {
+            chars.next();
+        }
+        let axis = match chars.peek() {
+            Some('x') => Some(0),
+            Some('y') => Some(1),
+            Some('z') => Some(2),
+            _ => None,
+        };
+        match (axis, value) {
+            (Some(axis), value) => {
+                chars.next();
+                coefficients[axis] += sign * value.unwrap_or(1.0);
+            }
+            (None, Some(value)) => constant += sign * value,
+            (None, None) => return None,
+        }
+    }
+    Some((
+        (coefficients[0], coefficients[1], coefficients[2]),
+        constant,
+    ))
+}
+
+/// Computes the cell vectors from the lengths and angles (in degrees)
+fn cell_vectors(lengths: Vector, angles: Vector) -> [Vector; 3] {
+    let (a, b, c) = lengths;
+    let cos = |angle: f64| {
+        let cos = angle.to_radians().cos();
+        if cos.abs() < RIGHT_ANGLE_COSINE {
+            0.0
+        } else {
+            cos
+        }
+    };
+    let (cos_alpha, cos_beta, cos_gamma) = (cos(angles.0), cos(angles.1), cos(angles.2));
+    let sin_gamma = angles.2.to_radians().sin();
+    let cy = (cos_alpha - cos_beta * cos_gamma) / sin_gamma;
+    let cz = (1.0 - cos_beta.powi(2) - cy.powi(2)).max(0.0).sqrt();
+    [
+        (a, 0.0, 0.0),
+        (b * cos_gamma, b * sin_gamma, 0.0),
+        (c * cos_beta, c * cy, c * cz),
+    ]
+}
+
+/// Deserializes a lattice from a CIF file
+pub fn from_str_cif(source: &str) -> Result<Lattice> {
+    let tokens = tokenize(source);
+    let block = Block::parse(&tokens)?;
+
+    let lengths = (
+        block.number("_cell_length_a")?,
+        block.number("_cell_length_b")?,
+        block.number("_cell_length_c")?,
+    );
+    let angles = (
+        block.number("_cell_angle_alpha")?,
+        block.number("_cell_angle_beta")?,
+        block.number("_cell_angle_gamma")?,
+    );
+    let lattice =
+        Lattice::try_new((1.0, 1.0, 1.0))?.try_with_cell(cell_vectors(lengths, angles))?;
+
+    let missing = |tag: &str| error(block.line, format!("missing `{}`", tag));
+    let xs = block
+        .column("_atom_site_fract_x")
+        .ok_or_else(|| missing("_atom_site_fract_x"))?;
+    let ys = block
+        .column("_atom_site_fract_y")
+        .ok_or_else(|| missing("_atom_site_fract_y"))?;
+    let zs = block
+        .column("_atom_site_fract_z")
+        .ok_or_else(|| missing("_atom_site_fract_z"))?;
+    let kinds = block
+        .column("_atom_site_type_symbol")
+        .or_else(|| block.column("_atom_site_label"))
+        .ok_or_else(|| missing("_atom_site_label"))?;
+
+    let operations = match block
+        .column("_symmetry_equiv_pos_as_xyz")
+        .or_else(|| block.column("_space_group_symop_operation_xyz"))
+    {
+        Some(column) => column
+            .into_iter()
+            .map(Operation::parse)
+            .collect::<Result<Vec<_>>>()?,
+        None => vec![Operation {
+            rotation: [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)],
+            translation: (0.0, 0.0, 0.0),
+        }],
+    };
+
+    let mut fractional: Vec<Vector> = Vec::new();
+    let mut sites = Vec::new();
+    for (((x, y), z), kind) in xs.iter().zip(ys).zip(zs).zip(kinds) {
+        let position = (number(x)?, number(y)?, number(z)?);
+        for operation in operations.iter() {
+            let image = operation.apply(position);
+            let coincident = fractional.iter().any(|other| {
+                let close = |a: f64, b: f64| (a - b - (a - b).round()).abs() < TOLERANCE;
+                close(image.0, other.0) && close(image.1, other.1) && close(image.2, other.2)
+            });
+            if !coincident {
+                fractional.push(image);
+                sites.push(Site::new(kind.value).with_position(lattice.to_cartesian(image)));
+            }
+        }
+    }
+
+    lattice.try_with_sites(sites)
+}
+
+/// Deserializes a lattice from a reader with a CIF file
+pub fn from_reader_cif<R>(mut reader: R) -> Result<Lattice>
+where
+    R: Read,
+{
+    let mut source = String::new();
+    reader.read_to_string(&mut source)?;
+    from_str_cif(&source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_str_cif, parse_component};
+
+    const IRON: &str = "
+data_Fe
+_chemical_name_common 'alpha iron'
+_cell_length_a 2.8665(2)
+_cell_length_b 2.8665(2)
+_cell_length_c 2.8665(2)
+_cell_angle_alpha 90
+_cell_angle_beta 90
+_cell_angle_gamma 90
+_publ_section_comment
+;
+Text fields are ignored
+;
+loop_
+_symmetry_equiv_pos_as_xyz
+'x, y, z'
+'-x, -y, -z'
+'x+1/2, y+1/2, z+1/2'
+loop_
+_atom_site_label
+_atom_site_type_symbol
+_atom_site_fract_x
+_atom_site_fract_y
+_atom_site_fract_z
+Fe1 Fe 0.0 0.0 0.0 # the only atom
+";
+
+    #[test]
+    fn components_are_parsed() {
+        assert_eq!(parse_component("x"), Some(((1.0, 0.0, 0.0), 0.0)));
+        assert_eq!(parse_component("-y+1/2"), Some(((0.0, -1.0, 0.0), 0.5)));
+        assert_eq!(parse_component("x-y"), Some(((1.0, -1.0, 0.0), 0.0)));
+        assert_eq!(parse_component("0.25+Z"), Some(((0.0, 0.0, 1.0), 0.25)));
+        assert_eq!(parse_component("2*x"), Some(((2.0, 0.0, 0.0), 0.0)));
+        assert_eq!(parse_component("x+q"), None);
+    }
+
+    #[test]
+    fn cif_is_expanded_with_symmetry_operations() {
+        let lattice = from_str_cif(IRON).unwrap();
+        assert_eq!(lattice.size(), (2.8665, 2.8665, 2.8665));
+        assert_eq!(lattice.sites().len(), 2);
+        assert_eq!(lattice.site(1).kind(), "Fe");
+        let (x, y, z) = lattice.site(1).position();
+        assert!((x - 1.43325).abs() + (y - 1.43325).abs() + (z - 1.43325).abs() < 1e-10);
+        assert!(lattice.edges().is_empty());
+        assert_eq!(lattice.vectors()[1], (0.0, 1.0, 0.0));
+        assert_eq!(lattice.vectors()[2], (0.0, 0.0, 1.0));
+    }
+
+    #[test]
+    fn cif_merges_rounded_coordinates() {
+        let data = "
+data_Co
+_cell_length_a 2.507
+_cell_length_b 2.507
+_cell_length_c 4.07
+_cell_angle_alpha 90
+_cell_angle_beta 90
+_cell_angle_gamma 120
+loop_
+_space_group_symop_operation_xyz
+x,y,z
+-y,x-y,z
+-x+y,-x,z
+-x,-y,z+1/2
+y,-x+y,z+1/2
+x-y,x,z+1/2
+loop_
+_atom_site_label
+_atom_site_fract_x
+_atom_site_fract_y
+_atom_site_fract_z
+Co 0.333 0.667 0.25
+";
+        let lattice = from_str_cif(data).unwrap();
+        assert_eq!(lattice.sites().len(), 2);
+    }
+
+    #[test]
+    fn cif_reads_hexagonal_cells() {
+        let data = "
+data_Co
+_cell_length_a 2.507
+_cell_length_b 2.507
+_cell_length_c 4.07
+_cell_angle_alpha 90
+_cell_angle_beta 90
+_cell_angle_gamma 120
+loop_
+_space_group_symop_operation_xyz
+x,y,z
+-y,x-y,z
+-x+y,-x,z
+-x,-y,z+1/2
+y,-x+y,z+1/2
+x-y,x,z+1/2
+loop_
+_atom_site_label
+_atom_site_fract_x
+_atom_site_fract_y
+_atom_site_fract_z
+Co 0.3333 0.6667 0.25
+";
+        let lattice = from_str_cif(data).unwrap();
+        assert_eq!(lattice.sites().len(), 2);
+        let (x, y, _) = lattice.vectors()[1];
+        assert!((x + 0.5).abs() < 1e-10);
+        assert!((y - 0.75_f64.sqrt()).abs() < 1e-10);
+    }
+
+    #[test]
+    fn cif_without_cell_fails() {
+        let data = "data_x\n_cell_length_a 1.0\n";
+        let error = from_str_cif(data).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error at line 1: missing `_cell_length_b`"
+        );
+    }
+}
@@ -0,0 +1,95 @@
+//! Graphviz DOT files
+//!
+//! Sites are the nodes, labelled with their kind and carrying their position
+//! and tags as attributes, and edges carry their delta and tags. Tags are
+//! joined with commas and omitted when there are none.
+
+use super::into_string;
+use crate::{Lattice, Tagged, error::Result};
+use std::io::Write;
+
+/// Quotes a string as a DOT identifier
+fn quote(text: &str) -> String {
+    format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+/// Serializes a lattice to a writer as a DOT file
+pub fn to_writer_dot<W>(mut writer: W, lattice: &Lattice) -> Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, "graph lattice {{")?;
+    for (id, site) in lattice.sites().iter().enumerate() {
+        let (x, y, z) = site.position();
+        let kind = quote(site.kind());
+        write!(
+            writer,
+            "  {} [label={}, kind={}, x={}, y={}, z={}",
+            id, kind, kind, x, y, z
+        )?;
+        if let Some(tags) = site.tags() {
+            write!(writer, ", tags={}", quote(&tags.join(",")))?;
+        }
+        writeln!(writer, "];")?;
+    }
+    for edge in lattice.edges() {
+        let (dx, dy, dz) = edge.delta();
+        write!(
+            writer,
+            "  {} -- {} [delta=\"{},{},{}\"",
+            edge.source(),
+            edge.target(),
+            dx,
+            dy,
+            dz
+        )?;
+        if let Some(tags) = edge.tags() {
+            write!(writer, ", tags={}", quote(&tags.join(",")))?;
+        }
+        writeln!(writer, "];")?;
+    }
+    writeln!(writer, "}}")?;
+    Ok(())
+}
+
+/// Serializes a lattice to a string as a DOT file
+pub fn to_string_dot(lattice: &Lattice) -> Result<String> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_dot(&mut writer, lattice)?;
+    Ok(into_string(writer))
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_string_dot;
+    use crate::{Edge, Lattice, Site};
+
+    #[test]
+    fn dot_writes_nodes_and_edges() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![
+                Site::new("Fe").with_tags(vec!["core", "shell"]),
+                Site::new("N\"i").with_position((0.5, 0.0, 0.0)),
+            ])
+            .unwrap()
+            .try_with_edges(vec![
+                Edge::new(0, 1, (0, 0, 0)).with_tags(vec!["nn1"]),
+                Edge::new(1, 0, (1, 0, 0)),
+            ])
+            .unwrap();
+        let data = to_string_dot(&lattice).unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        assert_eq!(lines[0], "graph lattice {");
+        assert_eq!(
+            lines[1],
+            r#"  0 [label="Fe", kind="Fe", x=0, y=0, z=0, tags="core,shell"];"#
+        );
+        assert_eq!(
+            lines[2],
+            r#"  1 [label="N\"i", kind="N\"i", x=0.5, y=0, z=0];"#
+        );
+        assert_eq!(lines[3], r#"  0 -- 1 [delta="0,0,0", tags="nn1"];"#);
+        assert_eq!(lines[4], r#"  1 -- 0 [delta="1,0,0"];"#);
+        assert_eq!(lines[5], "}");
+    }
+}
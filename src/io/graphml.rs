@@ -0,0 +1,126 @@
+//! GraphML files, for graph tools like NetworkX or Gephi
+//!
+//! Sites are the nodes, with their kind, position and tags as data, and edges
+//! carry their delta and tags. Tags are joined with commas and omitted when
+//! there are none. The graph is undirected, periodic edges may join the same
+//! pair of nodes more than once.
+
+use super::into_string;
+use crate::{Lattice, Tagged, error::Result};
+use std::io::Write;
+
+/// Escapes the characters that have a meaning in XML
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Serializes a lattice to a writer as a GraphML file
+pub fn to_writer_graphml<W>(mut writer: W, lattice: &Lattice) -> Result<()>
+where
+    W: Write,
+{
+    writeln!(writer, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+    writeln!(
+        writer,
+        r#"<graphml xmlns="http://graphml.graphdrawing.org/xmlns">"#
+    )?;
+    let keys = [
+        ("kind", "node", "kind", "string"),
+        ("x", "node", "x", "double"),
+        ("y", "node", "y", "double"),
+        ("z", "node", "z", "double"),
+        ("site_tags", "node", "tags", "string"),
+        ("dx", "edge", "dx", "int"),
+        ("dy", "edge", "dy", "int"),
+        ("dz", "edge", "dz", "int"),
+        ("edge_tags", "edge", "tags", "string"),
+    ];
+    for (id, domain, name, kind) in keys {
+        writeln!(
+            writer,
+            r#"  <key id="{}" for="{}" attr.name="{}" attr.type="{}"/>"#,
+            id, domain, name, kind
+        )?;
+    }
+    writeln!(writer, r#"  <graph id="lattice" edgedefault="undirected">"#)?;
+    for (id, site) in lattice.sites().iter().enumerate() {
+        let (x, y, z) = site.position();
+        write!(writer, r#"    <node id="n{}">"#, id)?;
+        write!(writer, r#"<data key="kind">{}</data>"#, escape(site.kind()))?;
+        write!(writer, r#"<data key="x">{}</data>"#, x)?;
+        write!(writer, r#"<data key="y">{}</data>"#, y)?;
+        write!(writer, r#"<data key="z">{}</data>"#, z)?;
+        if let Some(tags) = site.tags() {
+            write!(
+                writer,
+                r#"<data key="site_tags">{}</data>"#,
+                escape(&tags.join(","))
+            )?;
+        }
+        writeln!(writer, "</node>")?;
+    }
+    for edge in lattice.edges() {
+        let (dx, dy, dz) = edge.delta();
+        write!(
+            writer,
+            r#"    <edge source="n{}" target="n{}">"#,
+            edge.source(),
+            edge.target()
+        )?;
+        write!(writer, r#"<data key="dx">{}</data>"#, dx)?;
+        write!(writer, r#"<data key="dy">{}</data>"#, dy)?;
+        write!(writer, r#"<data key="dz">{}</data>"#, dz)?;
+        if let Some(tags) = edge.tags() {
+            write!(
+                writer,
+                r#"<data key="edge_tags">{}</data>"#,
+                escape(&tags.join(","))
+            )?;
+        }
+        writeln!(writer, "</edge>")?;
+    }
+    writeln!(writer, "  </graph>")?;
+    writeln!(writer, "</graphml>")?;
+    Ok(())
+}
+
+/// Serializes a lattice to a string as a GraphML file
+pub fn to_string_graphml(lattice: &Lattice) -> Result<String> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_graphml(&mut writer, lattice)?;
+    Ok(into_string(writer))
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_string_graphml;
+    use crate::{Edge, Lattice, Site};
+
+    #[test]
+    fn graphml_writes_nodes_and_edges() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![Site::new("Fe&Ni").with_tags(vec!["core"])])
+            .unwrap()
+            .try_with_edges(vec![Edge::new(0, 0, (1, 0, 0)).with_tags(vec!["nn1"])])
+            .unwrap();
+        let data = to_string_graphml(&lattice).unwrap();
+        assert!(data.contains(
+            r#"<node id="n0"><data key="kind">Fe&amp;Ni</data><data key="x">0</data><data key="y">0</data><data key="z">0</data><data key="site_tags">core</data></node>"#
+        ));
+        assert!(data.contains(
+            r#"<edge source="n0" target="n0"><data key="dx">1</data><data key="dy">0</data><data key="dz">0</data><data key="edge_tags">nn1</data></edge>"#
+        ));
+        assert!(data.ends_with("</graphml>\n"));
+    }
+}
@@ -0,0 +1,159 @@
+//! LAMMPS data files
+//!
+//! Atom types are numbered following the order in which kinds first appear in
+//! the lattice, and bond types following the order in which the tags of the
+//! edges first appear, edges without tags share a type of their own. Both
+//! maps are written as comments in the header.
+//!
+//! The box is written as a restricted triclinic box, _a_ along the _x_ axis
+//! and _b_ in the _xy_ plane, the tilt factors are only written when the cell
+//! is not orthogonal. Edges joining a site with itself can not be represented
+//! as bonds, so they are skipped.
+
+use super::into_string;
+use crate::{
+    Lattice, Tagged,
+    error::Result,
+    util::{cross, distinct, dot, norm},
+};
+use std::io::Write;
+
+/// Serializes a lattice to a writer as a LAMMPS data file, `bonds` controls
+/// whether the edges are written as bonds
+pub fn to_writer_lammps<W>(mut writer: W, lattice: &Lattice, bonds: bool) -> Result<()>
+where
+    W: Write,
+{
+    let kinds = distinct(lattice.sites().iter().map(|site| site.kind()));
+    let edges: Vec<_> = if bonds {
+        lattice
+            .edges()
+            .iter()
+            .filter(|edge| edge.source() != edge.target())
+            .collect()
+    } else {
+        Vec::new()
+    };
+    let label = |tags: Option<Vec<&str>>| tags.map(|tags| tags.join(","));
+    let bond_types = distinct(edges.iter().map(|edge| label(edge.tags())));
+
+    // Restricted triclinic cell, see the LAMMPS documentation on boxes
+    let [a, b, c] = lattice.cell();
+    let ax = norm(a);
+    let bx = dot(b, a) / ax;
+    let by = norm(cross(a, b)) / ax;
+    let cx = dot(c, a) / ax;
+    let cy = (dot(b, c) - bx * cx) / by;
+    let cz = (dot(c, c) - cx * cx - cy * cy).max(0.0).sqrt();
+
+    writeln!(writer, "LAMMPS data file written by vegas-lattice")?;
+    writeln!(writer)?;
+    let types: Vec<_> = kinds
+        .iter()
+        .enumerate()
+        .map(|(i, kind)| format!("{} {}", i + 1, kind))
+        .collect();
+    writeln!(writer, "# Atom types: {}", types.join(", "))?;
+    if bonds {
+        let types: Vec<_> = bond_types
+            .iter()
+            .enumerate()
+            .map(|(i, tags)| format!("{} {}", i + 1, tags.as_deref().unwrap_or("untagged")))
+            .collect();
+        writeln!(writer, "# Bond types: {}", types.join(", "))?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "{} atoms", lattice.sites().len())?;
+    writeln!(writer, "{} atom types", kinds.len())?;
+    if bonds {
+        writeln!(writer, "{} bonds", edges.len())?;
+        writeln!(writer, "{} bond types", bond_types.len())?;
+    }
+    writeln!(writer)?;
+    writeln!(writer, "0 {} xlo xhi", ax)?;
+    writeln!(writer, "0 {} ylo yhi", by)?;
+    writeln!(writer, "0 {} zlo zhi", cz)?;
+    if bx != 0.0 || cx != 0.0 || cy != 0.0 {
+        writeln!(writer, "{} {} {} xy xz yz", bx, cx, cy)?;
+    }
+
+    writeln!(writer)?;
+    writeln!(writer, "Atoms # {}", if bonds { "bond" } else { "atomic" })?;
+    writeln!(writer)?;
+    for (id, site) in lattice.sites().iter().enumerate() {
+        let (u, v, w) = lattice.to_fractional(site.position())?;
+        let (x, y, z) = (u * ax + v * bx + w * cx, v * by + w * cy, w * cz);
+        let kind = kinds.iter().position(|k| *k == site.kind()).unwrap_or(0) + 1;
+        if bonds {
+            writeln!(writer, "{} 1 {} {} {} {}", id + 1, kind, x, y, z)?;
+        } else {
+            writeln!(writer, "{} {} {} {} {}", id + 1, kind, x, y, z)?;
+        }
+    }
+
+    if !edges.is_empty() {
+        writeln!(writer)?;
+        writeln!(writer, "Bonds")?;
+        writeln!(writer)?;
+        for (id, edge) in edges.iter().enumerate() {
+            let tags = label(edge.tags());
+            let kind = bond_types.iter().position(|t| *t == tags).unwrap_or(0) + 1;
+            writeln!(
+                writer,
+                "{} {} {} {}",
+                id + 1,
+                kind,
+                edge.source() + 1,
+                edge.target() + 1
+            )?;
+        }
+    }
+    Ok(())
+}
+
+/// Serializes a lattice to a string as a LAMMPS data file
+pub fn to_string_lammps(lattice: &Lattice, bonds: bool) -> Result<String> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_lammps(&mut writer, lattice, bonds)?;
+    Ok(into_string(writer))
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_string_lammps;
+    use crate::Lattice;
+
+    #[test]
+    fn lammps_writes_atoms() {
+        let data = to_string_lammps(&Lattice::bcc(2.0), false).unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        assert_eq!(lines[2], "# Atom types: 1 A, 2 B");
+        assert_eq!(lines[4], "2 atoms");
+        assert_eq!(lines[5], "2 atom types");
+        assert_eq!(lines[7], "0 2 xlo xhi");
+        assert_eq!(lines[11], "Atoms # atomic");
+        assert_eq!(lines[13], "1 1 0 0 0");
+        assert_eq!(lines[14], "2 2 1 1 1");
+        assert!(!data.contains("Bonds"));
+        assert!(!data.contains("xy xz yz"));
+    }
+
+    #[test]
+    fn lammps_writes_bonds_typed_by_tags() {
+        let lattice = Lattice::bcc(1.0)
+            .expand_all(2)
+            .try_with_neighbor_shells(2)
+            .unwrap();
+        let data = to_string_lammps(&lattice, true).unwrap();
+        assert!(data.contains("# Bond types: 1 nn1, 2 nn2"));
+        assert!(data.contains("112 bonds"));
+        assert!(data.contains("Atoms # bond"));
+        assert!(data.contains("\nBonds\n"));
+    }
+
+    #[test]
+    fn lammps_writes_tilt_factors() {
+        let data = to_string_lammps(&Lattice::hcp(1.0, 1.6), false).unwrap();
+        assert!(data.contains("-0.5 0 0 xy xz yz"));
+    }
+}
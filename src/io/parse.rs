@@ -0,0 +1,67 @@
+//! Small helpers shared by the readers of text based formats
+
+use crate::error::{Result, VegasLatticeError};
+use std::str::FromStr;
+
+/// Builds a parse error for the given line
+pub(super) fn error(line: usize, message: impl Into<String>) -> VegasLatticeError {
+    VegasLatticeError::ParseError {
+        line,
+        message: message.into(),
+    }
+}
+
+/// Parses the token at `index` of a line
+pub(super) fn field<T: FromStr>(line: usize, tokens: &[&str], index: usize) -> Result<T> {
+    let token = tokens
+        .get(index)
+        .ok_or_else(|| error(line, format!("missing field {}", index + 1)))?;
+    token
+        .parse()
+        .map_err(|_| error(line, format!("invalid value `{}`", token)))
+}
+
+/// Parses three consecutive tokens of a line starting at `index`
+pub(super) fn triplet<T: FromStr>(line: usize, tokens: &[&str], index: usize) -> Result<(T, T, T)> {
+    Ok((
+        field(line, tokens, index)?,
+        field(line, tokens, index + 1)?,
+        field(line, tokens, index + 2)?,
+    ))
+}
+
+/// Iterates over the meaningful lines of a text, split in whitespace
+/// separated tokens and keeping track of line numbers (starting at 1)
+pub(super) struct Lines<'a> {
+    lines: Vec<(usize, Vec<&'a str>)>,
+    position: usize,
+}
+
+impl<'a> Lines<'a> {
+    /// Keeps only the lines for which `keep` returns `true` given the line
+    /// number and its contents
+    pub(super) fn new<F>(source: &'a str, keep: F) -> Self
+    where
+        F: Fn(usize, &str) -> bool,
+    {
+        let lines = source
+            .lines()
+            .enumerate()
+            .map(|(i, line)| (i + 1, line))
+            .filter(|&(i, line)| keep(i, line))
+            .map(|(i, line)| (i, line.split_whitespace().collect()))
+            .collect();
+        Lines { lines, position: 0 }
+    }
+
+    /// Returns the next line number and its tokens
+    pub(super) fn next(&mut self) -> Result<(usize, &[&'a str])> {
+        let last = self.lines.last().map(|(line, _)| *line).unwrap_or(0);
+        let (line, tokens) = self
+            .lines
+            .get(self.position)
+            .ok_or_else(|| error(last + 1, "unexpected end of file"))?;
+        self.position += 1;
+        Ok((*line, tokens))
+    }
+}
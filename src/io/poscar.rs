@@ -0,0 +1,236 @@
+//! VASP POSCAR and CONTCAR files
+//!
+//! Sites are grouped by kind when writing, following the order in which the
+//! kinds first appear in the lattice, and positions are written in `Direct`
+//! (fractional) coordinates. When reading, the species line becomes the site
+//! kinds, files without it (VASP 4 style) get the index of the species as
+//! kind. Edges are not part of the format, so they are lost, and lattices
+//! without sites can not be written since the species line would be empty.
+
+use super::into_string;
+use super::parse::{Lines, error, field, triplet};
+use crate::{
+    Lattice, Site, SiteRef,
+    error::{Result, VegasLatticeError},
+    util::{determinant, distinct, scale},
+};
+use std::io::{Read, Write};
+
+/// Serializes a lattice to a writer as a POSCAR file
+pub fn to_writer_poscar<W>(mut writer: W, lattice: &Lattice) -> Result<()>
+where
+    W: Write,
+{
+    if lattice.sites().is_empty() {
+        return Err(VegasLatticeError::EmptyLattice);
+    }
+    let kinds = distinct(lattice.sites().iter().map(|site| site.kind()));
+    let groups: Vec<Vec<SiteRef>> = kinds
+        .iter()
+        .map(|kind| {
+            lattice
+                .sites()
+                .iter()
+                .filter(|site| site.kind() == *kind)
+                .collect()
+        })
+        .collect();
+
+    writeln!(writer, "{}", kinds.join(" "))?;
+    writeln!(writer, "1.0")?;
+    for (x, y, z) in lattice.cell() {
+        writeln!(writer, "{} {} {}", x, y, z)?;
+    }
+    writeln!(writer, "{}", kinds.join(" "))?;
+    let counts: Vec<_> = groups.iter().map(|group| group.len().to_string()).collect();
+    writeln!(writer, "{}", counts.join(" "))?;
+    writeln!(writer, "Direct")?;
+    for site in groups.into_iter().flatten() {
+        let (x, y, z) = lattice.to_fractional(site.position())?;
+        writeln!(writer, "{} {} {}", x, y, z)?;
+    }
+    Ok(())
+}
+
+/// Serializes a lattice to a string as a POSCAR file
+pub fn to_string_poscar(lattice: &Lattice) -> Result<String> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_poscar(&mut writer, lattice)?;
+    Ok(into_string(writer))
+}
+
+/// Deserializes a lattice from a POSCAR file
+pub fn from_str_poscar(source: &str) -> Result<Lattice> {
+    // The first line is a free comment, so it is skipped even if empty
+    let mut lines = Lines::new(source, |number, line| number > 1 && !line.trim().is_empty());
+
+    let (line, tokens) = lines.next()?;
+    let factor: f64 = field(line, tokens, 0)?;
+    let mut cell = [(0.0, 0.0, 0.0); 3];
+    for vector in cell.iter_mut() {
+        let (line, tokens) = lines.next()?;
+        *vector = triplet(line, tokens, 0)?;
+    }
+    // A negative scaling factor is the volume of the cell
+    let factor = if factor < 0.0 {
+        (-factor / determinant(&cell).abs()).cbrt()
+    } else {
+        factor
+    };
+    let cell = cell.map(|vector| scale(vector, factor));
+    let lattice = Lattice::try_new((1.0, 1.0, 1.0))?.try_with_cell(cell)?;
+
+    let (line, tokens) = lines.next()?;
+    let (kinds, counts): (Vec<String>, Vec<usize>) = if tokens[0].parse::<usize>().is_ok() {
+        let counts = (0..tokens.len())
+            .map(|i| field(line, tokens, i))
+            .collect::<Result<Vec<_>>>()?;
+        ((0..counts.len()).map(|i| i.to_string()).collect(), counts)
+    } else {
+        let kinds = tokens.iter().map(|kind| kind.to_string()).collect();
+        let (line, tokens) = lines.next()?;
+        let counts = (0..tokens.len())
+            .map(|i| field(line, tokens, i))
+            .collect::<Result<Vec<_>>>()?;
+        (kinds, counts)
+    };
+    if kinds.len() != counts.len() {
+        return Err(error(line, "species and counts do not match"));
+    }
+
+    let (mut line, mut tokens) = lines.next()?;
+    if tokens[0].starts_with(['S', 's']) {
+        (line, tokens) = lines.next()?;
+    }
+    let cartesian = match tokens[0].chars().next() {
+        Some('C' | 'c' | 'K' | 'k') => true,
+        Some('D' | 'd') => false,
+        _ => return Err(error(line, format!("unknown coordinates `{}`", tokens[0]))),
+    };
+
+    let mut sites = Vec::with_capacity(counts.iter().sum());
+    for (kind, count) in kinds.iter().zip(counts) {
+        for _ in 0..count {
+            let (line, tokens) = lines.next()?;
+            let position = triplet(line, tokens, 0)?;
+            let position = if cartesian {
+                scale(position, factor)
+            } else {
+                lattice.to_cartesian(position)
+            };
+            sites.push(Site::new(kind).with_position(position));
+        }
+    }
+
+    lattice.try_with_sites(sites)
+}
+
+/// Deserializes a lattice from a reader with a POSCAR file
+pub fn from_reader_poscar<R>(mut reader: R) -> Result<Lattice>
+where
+    R: Read,
+{
+    let mut source = String::new();
+    reader.read_to_string(&mut source)?;
+    from_str_poscar(&source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_str_poscar, to_string_poscar};
+    use crate::{Lattice, Site, error::VegasLatticeError};
+
+    #[test]
+    fn poscar_groups_sites_by_kind() {
+        let lattice = Lattice::sc(2.0)
+            .try_with_edges(vec![])
+            .unwrap()
+            .try_with_sites(vec![
+                Site::new("Fe"),
+                Site::new("Ni").with_position((1.0, 0.0, 0.0)),
+                Site::new("Fe").with_position((0.0, 1.0, 0.0)),
+            ])
+            .unwrap();
+        let data = to_string_poscar(&lattice).unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        assert_eq!(lines[2], "2 0 0");
+        assert_eq!(lines[5], "Fe Ni");
+        assert_eq!(lines[6], "2 1");
+        assert_eq!(lines[7], "Direct");
+        assert_eq!(lines[8], "0 0 0");
+        assert_eq!(lines[9], "0 0.5 0");
+        assert_eq!(lines[10], "0.5 0 0");
+    }
+
+    #[test]
+    fn poscar_can_be_read_back() {
+        let lattice = Lattice::hcp(2.5, 4.0);
+        let data = to_string_poscar(&lattice).unwrap();
+        let output = from_str_poscar(&data).unwrap();
+        assert!((output.size().0 - 2.5).abs() < 1e-10);
+        assert!((output.size().2 - 4.0).abs() < 1e-10);
+        assert_eq!(output.sites().len(), 2);
+        assert_eq!(output.site(1).kind(), "B");
+        let (x, y, z) = output.site(1).position();
+        let (u, v, w) = lattice.site(1).position();
+        assert!((x - u).abs() + (y - v).abs() + (z - w).abs() < 1e-10);
+    }
+
+    #[test]
+    fn poscar_reads_cartesian_coordinates() {
+        let data = "bcc Fe
+            2.0
+            1.0 0.0 0.0
+            0.0 1.0 0.0
+            0.0 0.0 1.0
+            Fe
+            2
+            Selective dynamics
+            Cartesian
+            0.0 0.0 0.0 T T T
+            0.5 0.5 0.5 T T T
+        ";
+        let lattice = from_str_poscar(data).unwrap();
+        assert_eq!(lattice.size(), (2.0, 2.0, 2.0));
+        assert_eq!(lattice.site(1).kind(), "Fe");
+        assert_eq!(lattice.site(1).position(), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn poscar_reads_old_files_and_volumes() {
+        let data = "
+            -8.0
+            1.0 0.0 0.0
+            0.0 1.0 0.0
+            0.0 0.0 1.0
+            1 1
+            direct
+            0.0 0.0 0.0
+            0.5 0.5 0.5
+        ";
+        let lattice = from_str_poscar(data).unwrap();
+        assert_eq!(lattice.size(), (2.0, 2.0, 2.0));
+        assert_eq!(lattice.site(0).kind(), "0");
+        assert_eq!(lattice.site(1).kind(), "1");
+        assert_eq!(lattice.site(1).position(), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn poscar_refuses_lattices_without_sites() {
+        let lattice = Lattice::try_new((1.0, 1.0, 1.0)).unwrap();
+        assert!(matches!(
+            to_string_poscar(&lattice),
+            Err(VegasLatticeError::EmptyLattice)
+        ));
+    }
+
+    #[test]
+    fn poscar_fails_on_missing_sites() {
+        let data = "Fe\n1.0\n1 0 0\n0 1 0\n0 0 1\nFe\n2\nDirect\n0 0 0\n";
+        let error = from_str_poscar(data).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error at line 10: unexpected end of file"
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Vampire unit cell files
+//!
+//! See the [Vampire manual](https://vampire.york.ac.uk/resources/) for the
+//! details of the format. Atom positions are written in fractional coordinates
+//! and materials are numbered following the alphabetical order of the kinds,
+//! so the numbering does not depend on the order of the sites.
+//!
+//! The names of the materials are stored in comments like `# Material 0 Fe`,
+//! so they can be read back into site kinds, files without them get the
+//! material ids as kinds.
+
+use super::into_string;
+use super::parse::{Lines, error, field, triplet};
+use crate::{Edge, Exchange, Lattice, Site, error::Result};
+use std::{
+    collections::{BTreeSet, HashMap},
+    io::{Read, Write},
+};
+
+/// Serializes a lattice to a writer as a Vampire unit cell file
+pub fn to_writer_vampire<W>(mut writer: W, lattice: &Lattice) -> Result<()>
+where
+    W: Write,
+{
+    let materials: Vec<_> = lattice
+        .sites()
+        .iter()
+        .map(|site| site.kind())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .collect();
+
+    writeln!(writer, "# Unit cell size:")?;
+    let (a, b, c) = lattice.size();
+    writeln!(writer, "{} {} {}", a, b, c)?;
+    writeln!(writer, "# Unit cell vectors:")?;
+    for (x, y, z) in lattice.vectors() {
+        writeln!(writer, "{} {} {}", x, y, z)?;
+    }
+
+    writeln!(
+        writer,
+        "# Atoms num_atoms num_materials; id cx cy cz mat lc hc"
+    )?;
+    for (id, kind) in materials.iter().enumerate() {
+        writeln!(writer, "# Material {} {}", id, kind)?;
+    }
+    writeln!(writer, "{} {}", lattice.sites().len(), materials.len())?;
+    for (id, site) in lattice.sites().iter().enumerate() {
+        let (x, y, z) = lattice.to_fractional(site.position())?;
+        // The material list is built from the sites, so the kind is always there
+        let material = materials.binary_search(&site.kind()).unwrap_or_default();
+        writeln!(writer, "{} {} {} {} {} 0 0", id, x, y, z, material)?;
+    }
+
+    writeln!(writer, "# Interactions n exctype; id i j dx dy dz Jij")?;
+    let tensorial = lattice
+        .edges()
+        .iter()
+        .any(|edge| matches!(edge.exchange(), Some(Exchange::Tensor(_))));
+    let kind = if tensorial { "tensorial" } else { "isotropic" };
+    writeln!(writer, "{} {}", lattice.edges().len(), kind)?;
+    for (id, edge) in lattice.edges().iter().enumerate() {
+        let (dx, dy, dz) = edge.delta();
+        write!(
+            writer,
+            "{} {} {} {} {} {}",
+            id,
+            edge.source(),
+            edge.target(),
+            dx,
+            dy,
+            dz
+        )?;
+        let exchange = edge.exchange().unwrap_or(Exchange::Isotropic(1.0));
+        match (tensorial, exchange) {
+            (false, Exchange::Isotropic(j)) => write!(writer, " {}", j)?,
+            _ => {
+                for (jx, jy, jz) in exchange.tensor() {
+                    write!(writer, " {} {} {}", jx, jy, jz)?;
+                }
+            }
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Serializes a lattice to a string as a Vampire unit cell file
+pub fn to_string_vampire(lattice: &Lattice) -> Result<String> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_vampire(&mut writer, lattice)?;
+    Ok(into_string(writer))
+}
+
+/// Deserializes a lattice from a Vampire unit cell file
+pub fn from_str_vampire(source: &str) -> Result<Lattice> {
+    let names: HashMap<usize, &str> = source
+        .lines()
+        .filter_map(|line| {
+            let comment = line.trim().strip_prefix('#')?;
+            match comment.split_whitespace().collect::<Vec<_>>()[..] {
+                ["Material", id, name] => id.parse().ok().map(|id| (id, name)),
+                _ => None,
+            }
+        })
+        .collect();
+    let mut lines = Lines::new(source, |_, line| {
+        let line = line.trim();
+        !line.is_empty() && !line.starts_with('#')
+    });
+
+    let (line, tokens) = lines.next()?;
+    let size = triplet(line, tokens, 0)?;
+    let mut vectors = [(0.0, 0.0, 0.0); 3];
+    for vector in vectors.iter_mut() {
+        let (line, tokens) = lines.next()?;
+        *vector = triplet(line, tokens, 0)?;
+    }
+    let lattice = Lattice::try_new(size)?.try_with_vectors(vectors)?;
+
+    let (line, tokens) = lines.next()?;
+    let n_sites: usize = field(line, tokens, 0)?;
+    let mut sites = Vec::with_capacity(n_sites);
+    for _ in 0..n_sites {
+        let (line, tokens) = lines.next()?;
+        let position = lattice.to_cartesian(triplet(line, tokens, 1)?);
+        let material: usize = field(line, tokens, 4)?;
+        let kind = match names.get(&material) {
+            Some(name) => name.to_string(),
+            None => material.to_string(),
+        };
+        sites.push(Site::new(&kind).with_position(position));
+    }
+
+    let (line, tokens) = lines.next()?;
+    let n_edges: usize = field(line, tokens, 0)?;
+    let exchange_type: String = field(line, tokens, 1)?;
+    let mut edges = Vec::with_capacity(n_edges);
+    for _ in 0..n_edges {
+        let (line, tokens) = lines.next()?;
+        let source = field(line, tokens, 1)?;
+        let target = field(line, tokens, 2)?;
+        let delta = triplet(line, tokens, 3)?;
+        let exchange = match exchange_type.as_str() {
+            "isotropic" => Exchange::Isotropic(field(line, tokens, 6)?),
+            "vectorial" => {
+                let (jx, jy, jz) = triplet(line, tokens, 6)?;
+                Exchange::Tensor([(jx, 0.0, 0.0), (0.0, jy, 0.0), (0.0, 0.0, jz)])
+            }
+            "tensorial" => Exchange::Tensor([
+                triplet(line, tokens, 6)?,
+                triplet(line, tokens, 9)?,
+                triplet(line, tokens, 12)?,
+            ]),
+            other => {
+                return Err(error(line, format!("unknown exchange type `{}`", other)));
+            }
+        };
+        edges.push(Edge::new(source, target, delta).with_exchange(exchange));
+    }
+
+    lattice.try_with_sites(sites)?.try_with_edges(edges)
+}
+
+/// Deserializes a lattice from a reader with a Vampire unit cell file
+pub fn from_reader_vampire<R>(mut reader: R) -> Result<Lattice>
+where
+    R: Read,
+{
+    let mut source = String::new();
+    reader.read_to_string(&mut source)?;
+    from_str_vampire(&source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_str_vampire, to_string_vampire};
+    use crate::{Edge, Exchange, Lattice, Site};
+
+    fn data_lines(data: &str) -> Vec<&str> {
+        data.lines().filter(|line| !line.starts_with('#')).collect()
+    }
+
+    #[test]
+    fn vampire_uses_fractional_coordinates() {
+        let data = to_string_vampire(&Lattice::bcc(2.0)).unwrap();
+        let lines = data_lines(&data);
+        assert_eq!(lines[0], "2 2 2");
+        assert_eq!(lines[4], "2 2");
+        assert_eq!(lines[5], "0 0 0 0 0 0 0");
+        assert_eq!(lines[6], "1 0.5 0.5 0.5 1 0 0");
+    }
+
+    #[test]
+    fn vampire_writes_the_lattice_vectors() {
+        let data = to_string_vampire(&Lattice::hcp(1.0, 1.6)).unwrap();
+        let lines = data_lines(&data);
+        assert_eq!(lines[0], "1 1 1.6");
+        assert_eq!(lines[2], "-0.5 0.8660254037844386 0");
+    }
+
+    #[test]
+    fn vampire_materials_do_not_depend_on_site_order() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![Site::new("Ni"), Site::new("Fe")])
+            .unwrap()
+            .try_with_edges(vec![])
+            .unwrap();
+        let data = to_string_vampire(&lattice).unwrap();
+        assert!(data.contains("# Material 0 Fe"));
+        let lines = data_lines(&data);
+        assert_eq!(lines[5], "0 0 0 0 1 0 0");
+        assert_eq!(lines[6], "1 0 0 0 0 0 0");
+    }
+
+    #[test]
+    fn vampire_writes_the_exchange() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_edges(vec![
+                Edge::new(0, 0, (1, 0, 0)).with_exchange(Exchange::Isotropic(-2.5)),
+                Edge::new(0, 0, (0, 1, 0)),
+            ])
+            .unwrap();
+        let data = to_string_vampire(&lattice).unwrap();
+        let lines = data_lines(&data);
+        assert_eq!(lines[6], "2 isotropic");
+        assert_eq!(lines[7], "0 0 0 1 0 0 -2.5");
+        assert_eq!(lines[8], "1 0 0 0 1 0 1");
+    }
+
+    #[test]
+    fn vampire_switches_to_tensorial_exchange() {
+        let tensor = [(1.0, 0.5, 0.0), (0.5, 1.0, 0.0), (0.0, 0.0, 1.0)];
+        let lattice = Lattice::sc(1.0)
+            .try_with_edges(vec![
+                Edge::new(0, 0, (1, 0, 0)).with_exchange(Exchange::Tensor(tensor)),
+                Edge::new(0, 0, (0, 1, 0)).with_exchange(Exchange::Isotropic(2.0)),
+            ])
+            .unwrap();
+        let data = to_string_vampire(&lattice).unwrap();
+        let lines = data_lines(&data);
+        assert_eq!(lines[6], "2 tensorial");
+        assert_eq!(lines[7], "0 0 0 1 0 0 1 0.5 0 0.5 1 0 0 0 1");
+        assert_eq!(lines[8], "1 0 0 0 1 0 2 0 0 0 2 0 0 0 2");
+    }
+
+    #[test]
+    fn vampire_can_be_read_back() {
+        let tensor = [(1.0, 0.5, 0.0), (0.5, 1.0, 0.0), (0.0, 0.0, 1.0)];
+        let lattice = Lattice::hcp(1.0, 1.6).alloy_sites(
+            "A",
+            crate::Alloy::try_new(vec!["Co"], vec![1]).unwrap(),
+            &mut rand::rng(),
+        );
+        let edges: Vec<_> = lattice
+            .edges()
+            .iter()
+            .map(|e| e.clone().with_exchange(Exchange::Tensor(tensor)))
+            .collect();
+        let lattice = lattice.try_with_edges(edges).unwrap();
+        let data = to_string_vampire(&lattice).unwrap();
+        let output = from_str_vampire(&data).unwrap();
+        assert_eq!(output.size(), lattice.size());
+        assert_eq!(output.vectors(), lattice.vectors());
+        assert_eq!(output.sites().len(), 2);
+        assert_eq!(output.site(0).kind(), "Co");
+        assert_eq!(output.site(1).kind(), "B");
+        let (x, y, z) = output.site(1).position();
+        let (u, v, w) = lattice.site(1).position();
+        assert!((x - u).abs() + (y - v).abs() + (z - w).abs() < 1e-10);
+        assert_eq!(output.edges().len(), 12);
+        assert_eq!(output.edges()[0].exchange(), Some(Exchange::Tensor(tensor)));
+    }
+
+    #[test]
+    fn vampire_files_without_names_use_material_ids() {
+        let data = "
+            # Unit cell size:
+            2.87 2.87 2.87
+            # Unit cell vectors:
+            1.0 0.0 0.0
+            0.0 1.0 0.0
+            0.0 0.0 1.0
+            # Atoms
+            2 2
+            0 0.0 0.0 0.0 0 0 0
+            1 0.5 0.5 0.5 1 1 0
+            # Interactions
+            2 vectorial
+            0 0 1 0 0 0 1.0 2.0 3.0
+            1 1 0 0 0 0 1.0 2.0 3.0
+        ";
+        let lattice = from_str_vampire(data).unwrap();
+        assert_eq!(lattice.site(0).kind(), "0");
+        assert_eq!(lattice.site(1).kind(), "1");
+        assert_eq!(lattice.site(1).position(), (1.435, 1.435, 1.435));
+        assert_eq!(
+            lattice.edges()[0].exchange(),
+            Some(Exchange::Tensor([
+                (1.0, 0.0, 0.0),
+                (0.0, 2.0, 0.0),
+                (0.0, 0.0, 3.0)
+            ]))
+        );
+    }
+
+    #[test]
+    fn vampire_reports_the_line_of_errors() {
+        let data = "1 1 1\n1 0 0\n0 1 0\n0 0 1\n1 1\n0 0 0 zero 0\n0 isotropic\n";
+        let error = from_str_vampire(data).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error at line 6: invalid value `zero`"
+        );
+    }
+
+    #[test]
+    fn vampire_fails_on_truncated_files() {
+        let data = "1 1 1\n1 0 0\n0 1 0\n0 0 1\n2 1\n0 0 0 0 0\n";
+        assert!(from_str_vampire(data).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+//! Legacy VTK files, readable by ParaView
+//!
+//! Sites are written as points of a polygonal dataset, with the kind as an
+//! integer `kind` scalar and as a `species` string array, and every tag found
+//! in the sites becomes an integer array with ones in the tagged sites. Kinds
+//! are numbered following the order in which they first appear, the numbering
+//! is written in the title of the file. Only edges within the lattice are
+//! written as lines, periodic edges would cross the whole sample.
+//!
+//! Kinds and tags are encoded the way VTK does it, blanks, quotes, percent
+//! signs and non ASCII bytes become `%` followed by their hexadecimal value,
+//! so `Fe 1` is written as `Fe%201`.
+
+use super::into_string;
+use crate::{Lattice, Tagged, error::Result, util::distinct};
+use std::io::Write;
+
+/// Encodes a name or string value so it is read back as a single token
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_graphic() && byte != b'%' && byte != b'"' {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Serializes a lattice to a writer as a legacy VTK file
+pub fn to_writer_vtk<W>(mut writer: W, lattice: &Lattice) -> Result<()>
+where
+    W: Write,
+{
+    let sites = lattice.sites();
+    let kinds = distinct(sites.iter().map(|site| site.kind()));
+    let tags = distinct(
+        sites
+            .iter()
+            .flat_map(|site| site.tags().unwrap_or_default()),
+    );
+    let edges: Vec<_> = lattice
+        .edges()
+        .iter()
+        .filter(|edge| edge.delta() == (0, 0, 0) && edge.source() != edge.target())
+        .collect();
+
+    writeln!(writer, "# vtk DataFile Version 3.0")?;
+    let names: Vec<_> = kinds
+        .iter()
+        .enumerate()
+        .map(|(i, kind)| format!("{} {}", i, encode(kind)))
+        .collect();
+    writeln!(writer, "vegas-lattice kinds: {}", names.join(", "))?;
+    writeln!(writer, "ASCII")?;
+    writeln!(writer, "DATASET POLYDATA")?;
+    writeln!(writer, "POINTS {} double", sites.len())?;
+    for site in sites {
+        let (x, y, z) = site.position();
+        writeln!(writer, "{} {} {}", x, y, z)?;
+    }
+    if !edges.is_empty() {
+        writeln!(writer, "LINES {} {}", edges.len(), 3 * edges.len())?;
+        for edge in edges {
+            writeln!(writer, "2 {} {}", edge.source(), edge.target())?;
+        }
+    }
+
+    writeln!(writer, "POINT_DATA {}", sites.len())?;
+    writeln!(writer, "SCALARS kind int 1")?;
+    writeln!(writer, "LOOKUP_TABLE default")?;
+    for site in sites {
+        let kind = kinds.iter().position(|k| *k == site.kind()).unwrap_or(0);
+        writeln!(writer, "{}", kind)?;
+    }
+    writeln!(writer, "FIELD FieldData {}", tags.len() + 1)?;
+    writeln!(writer, "species 1 {} string", sites.len())?;
+    for site in sites {
+        writeln!(writer, "{}", encode(site.kind()))?;
+    }
+    for tag in tags {
+        writeln!(writer, "{} 1 {} int", encode(tag), sites.len())?;
+        for site in sites {
+            writeln!(writer, "{}", if site.has_tag(tag) { 1 } else { 0 })?;
+        }
+    }
+    Ok(())
+}
+
+/// Serializes a lattice to a string as a legacy VTK file
+pub fn to_string_vtk(lattice: &Lattice) -> Result<String> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_vtk(&mut writer, lattice)?;
+    Ok(into_string(writer))
+}
+
+#[cfg(test)]
+mod test {
+    use super::to_string_vtk;
+    use crate::{Lattice, Site};
+
+    #[test]
+    fn vtk_writes_points_and_lines() {
+        let lattice = Lattice::sc(1.0).expand_x(3);
+        let data = to_string_vtk(&lattice).unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        assert_eq!(lines[1], "vegas-lattice kinds: 0 A");
+        assert_eq!(lines[4], "POINTS 3 double");
+        assert_eq!(lines[6], "1 0 0");
+        // The edge closing the periodic boundary is not a line
+        assert_eq!(lines[8], "LINES 2 6");
+        assert_eq!(lines[9], "2 0 1");
+        assert_eq!(lines[10], "2 1 2");
+        assert_eq!(lines[11], "POINT_DATA 3");
+    }
+
+    #[test]
+    fn vtk_writes_kinds_and_tags() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_edges(vec![])
+            .unwrap()
+            .try_with_sites(vec![
+                Site::new("Fe").with_tags(vec!["core"]),
+                Site::new("Ni"),
+            ])
+            .unwrap();
+        let data = to_string_vtk(&lattice).unwrap();
+        assert!(!data.contains("LINES"));
+        assert!(data.contains("SCALARS kind int 1\nLOOKUP_TABLE default\n0\n1\n"));
+        assert!(data.contains("FIELD FieldData 2\nspecies 1 2 string\nFe\nNi\n"));
+        assert!(data.contains("core 1 2 int\n1\n0\n"));
+    }
+
+    #[test]
+    fn vtk_encodes_blanks_in_kinds_and_tags() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![Site::new("Fe 1").with_tags(vec!["in core", "50%"])])
+            .unwrap();
+        let data = to_string_vtk(&lattice).unwrap();
+        assert!(data.contains("vegas-lattice kinds: 0 Fe%201\n"));
+        assert!(data.contains("species 1 1 string\nFe%201\n"));
+        assert!(data.contains("in%20core 1 1 int\n1\n"));
+        assert!(data.contains("50%25 1 1 int\n1\n"));
+    }
+}
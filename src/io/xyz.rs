@@ -0,0 +1,530 @@
+//! Extended XYZ files
+//!
+//! The comment line holds the cell vectors in the `Lattice` key, the columns
+//! in the `Properties` key and the periodicity in the `pbc` key, an axis is
+//! periodic if any edge still crosses the cell boundary along it. Every tag
+//! found in the sites gets its own logical column.
+//!
+//! When reading, the lattice is periodic along every axis, since it keeps
+//! its periodicity in edges and those are not read, so files with a `pbc`
+//! key marking some axis as non periodic are rejected.
+//!
+//! Plain XYZ files are supported as well, with no cell to go by their sites
+//! are taken as one period of a crystal, which is only a guess. The sites are
+//! moved so that the corner of their bounding box sits at the origin, and the
+//! cell is that bounding box padded along each axis by the smallest gap
+//! between the coordinates along it, so the periodic images keep the spacing
+//! of the sites. Axes where all the sites share a coordinate are padded by
+//! the smallest gap of the other axes, or by one when there is none.
+//!
+//! Logical columns become tags of the sites, the other extra columns are
+//! ignored, and so is everything after the first frame.
+//!
+//! Kinds and tags are encoded so they are read back as a single token, blanks,
+//! quotes, colons, percent signs and non ASCII bytes become `%` followed by
+//! their hexadecimal value, so `Fe 1` is written as `Fe%201`.
+
+use super::into_string;
+use super::parse::{Lines, error, field, triplet};
+use crate::{Lattice, Site, SiteRef, Supercell, Tagged, error::Result, util::distinct};
+use std::io::{Read, Write};
+
+/// Coordinates closer than this along an axis belong to the same plane
+const GAP_TOLERANCE: f64 = 1e-3;
+
+/// Encodes a kind or tag so it is read back as a single token
+fn encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_graphic() && !matches!(byte, b'%' | b'"' | b':') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+/// Decodes a kind or tag written by `encode`, a `%` that is not followed by
+/// two hexadecimal digits is kept as is
+fn decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = value
+            .get(i + 1..i + 3)
+            .filter(|_| bytes[i] == b'%')
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).unwrap_or_else(|_| value.to_string())
+}
+
+/// Writes the whole file, the tags and periodicity are taken from `lattice`
+/// while the sites come from the iterator, so it can be used for supercells
+fn write_xyz<'s, W, S>(
+    writer: &mut W,
+    lattice: &Lattice,
+    cell: [(f64, f64, f64); 3],
+    sites: (usize, S),
+) -> Result<()>
+where
+    W: Write,
+    S: Iterator<Item = SiteRef<'s>>,
+{
+    let tags = distinct(
+        lattice
+            .sites()
+            .iter()
+            .flat_map(|site| site.tags().unwrap_or_default()),
+    );
+    let periodic = |axis: fn((i32, i32, i32)) -> i32| {
+        if lattice.edges().iter().any(|edge| axis(edge.delta()) != 0) {
+            "T"
+        } else {
+            "F"
+        }
+    };
+
+    let (count, sites) = sites;
+    writeln!(writer, "{}", count)?;
+    let cell: Vec<_> = cell
+        .iter()
+        .map(|(x, y, z)| format!("{} {} {}", x, y, z))
+        .collect();
+    write!(writer, "Lattice=\"{}\" ", cell.join(" "))?;
+    write!(writer, "Properties=species:S:1:pos:R:3")?;
+    for tag in tags.iter() {
+        write!(writer, ":{}:L:1", encode(tag))?;
+    }
+    writeln!(
+        writer,
+        " pbc=\"{} {} {}\"",
+        periodic(|d| d.0),
+        periodic(|d| d.1),
+        periodic(|d| d.2)
+    )?;
+
+    for site in sites {
+        let (x, y, z) = site.position();
+        write!(writer, "{} {} {} {}", encode(site.kind()), x, y, z)?;
+        for tag in tags.iter() {
+            write!(writer, " {}", if site.has_tag(tag) { "T" } else { "F" })?;
+        }
+        writeln!(writer)?;
+    }
+    Ok(())
+}
+
+/// Serializes a lattice to a writer as an extended XYZ file
+pub fn to_writer_xyz<W>(mut writer: W, lattice: &Lattice) -> Result<()>
+where
+    W: Write,
+{
+    let sites = (lattice.sites().len(), lattice.sites().iter());
+    write_xyz(&mut writer, lattice, lattice.cell(), sites)
+}
+
+/// Serializes a supercell to a writer as an extended XYZ file, the sites are
+/// written as they are computed
+pub fn to_writer_xyz_supercell<W>(mut writer: W, supercell: &Supercell) -> Result<()>
+where
+    W: Write,
+{
+    let sites = (supercell.site_count(), supercell.sites());
+    write_xyz(&mut writer, supercell.lattice(), supercell.cell(), sites)
+}
+
+/// Serializes a lattice to a string as an extended XYZ file
+pub fn to_string_xyz(lattice: &Lattice) -> Result<String> {
+    let mut writer = Vec::with_capacity(128);
+    to_writer_xyz(&mut writer, lattice)?;
+    Ok(into_string(writer))
+}
+
+/// Splits the comment line of an extended XYZ file into its `key=value`
+/// pairs, keys are returned in lowercase and values without quotes
+fn key_values(comment: &str) -> Vec<(String, &str)> {
+    let mut pairs = Vec::new();
+    let mut rest = comment.trim_start();
+    while !rest.is_empty() {
+        let end = rest
+            .find(|c: char| c == '=' || c.is_whitespace())
+            .unwrap_or(rest.len());
+        let key = rest[..end].to_lowercase();
+        rest = &rest[end..];
+        if let Some(value) = rest.strip_prefix('=') {
+            let (value, remainder) = match value.strip_prefix('"') {
+                Some(quoted) => {
+                    let end = quoted.find('"').unwrap_or(quoted.len());
+                    (&quoted[..end], quoted.get(end + 1..).unwrap_or(""))
+                }
+                None => {
+                    let end = value.find(char::is_whitespace).unwrap_or(value.len());
+                    (&value[..end], &value[end..])
+                }
+            };
+            pairs.push((key, value));
+            rest = remainder;
+        }
+        rest = rest.trim_start();
+    }
+    pairs
+}
+
+/// A column of the `Properties` key
+#[derive(Debug)]
+struct Property<'a> {
+    name: &'a str,
+    kind: &'a str,
+    columns: usize,
+}
+
+/// Parses a `Properties` value like `species:S:1:pos:R:3`
+fn properties(line: usize, value: &str) -> Result<Vec<Property<'_>>> {
+    let fields: Vec<_> = value.split(':').collect();
+    if fields.len() % 3 != 0 {
+        return Err(error(line, format!("invalid properties `{}`", value)));
+    }
+    fields
+        .chunks(3)
+        .map(|chunk| {
+            let columns = chunk[2]
+                .parse()
+                .map_err(|_| error(line, format!("invalid properties `{}`", value)))?;
+            Ok(Property {
+                name: chunk[0],
+                kind: chunk[1],
+                columns,
+            })
+        })
+        .collect()
+}
+
+/// Deserializes a lattice from an XYZ or extended XYZ file, only sites are
+/// read
+pub fn from_str_xyz(source: &str) -> Result<Lattice> {
+    let comment = source.lines().nth(1).unwrap_or_default();
+    let pairs = key_values(comment);
+    let value = |key: &str| {
+        pairs
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, value)| *value)
+    };
+    let properties = properties(2, value("properties").unwrap_or("species:S:1:pos:R:3"))?;
+    let column = |name: &str| {
+        let mut start = 0;
+        for property in properties.iter() {
+            if property.name.eq_ignore_ascii_case(name) {
+                return Some(start);
+            }
+            start += property.columns;
+        }
+        None
+    };
+    let species = column("species").ok_or_else(|| error(2, "missing species property"))?;
+    let pos = column("pos").ok_or_else(|| error(2, "missing pos property"))?;
+    let tags: Vec<_> = properties
+        .iter()
+        .filter(|property| property.kind.eq_ignore_ascii_case("L") && property.columns == 1)
+        .filter_map(|property| Some((decode(property.name), column(property.name)?)))
+        .collect();
+
+    let mut lines = Lines::new(source, |number, line| {
+        number != 2 && !line.trim().is_empty()
+    });
+    let (line, tokens) = lines.next()?;
+    let count: usize = field(line, tokens, 0)?;
+    let mut sites = Vec::with_capacity(count);
+    for _ in 0..count {
+        let (line, tokens) = lines.next()?;
+        let kind = decode(&field::<String>(line, tokens, species)?);
+        let position = triplet(line, tokens, pos)?;
+        let mut site = Site::new(&kind).with_position(position);
+        let site_tags: Vec<_> = tags
+            .iter()
+            .filter(|(_, index)| tokens.get(*index).copied().and_then(flag) == Some(true))
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if !site_tags.is_empty() {
+            site = site.with_tags(site_tags);
+        }
+        sites.push(site);
+    }
+
+    if let Some(pbc) = value("pbc") {
+        let axes: Vec<_> = pbc.split_whitespace().map(flag).collect();
+        match axes[..] {
+            [Some(true), Some(true), Some(true)] => {}
+            [Some(_), Some(_), Some(_)] => {
+                return Err(error(
+                    2,
+                    format!("non periodic axes in pbc `{}` are not supported", pbc),
+                ));
+            }
+            _ => return Err(error(2, format!("invalid pbc `{}`", pbc))),
+        }
+    }
+
+    let lattice = match value("lattice") {
+        Some(cell) => {
+            let numbers = cell
+                .split_whitespace()
+                .map(|number| number.parse::<f64>())
+                .collect::<std::result::Result<Vec<_>, _>>()
+                .ok()
+                .filter(|numbers| numbers.len() == 9)
+                .ok_or_else(|| error(2, format!("invalid lattice `{}`", cell)))?;
+            Lattice::try_new((1.0, 1.0, 1.0))?.try_with_cell([
+                (numbers[0], numbers[1], numbers[2]),
+                (numbers[3], numbers[4], numbers[5]),
+                (numbers[6], numbers[7], numbers[8]),
+            ])?
+        }
+        None => {
+            let axes = [
+                extent(sites.iter().map(|site| site.position().0)),
+                extent(sites.iter().map(|site| site.position().1)),
+                extent(sites.iter().map(|site| site.position().2)),
+            ];
+            let fallback = axes
+                .iter()
+                .filter_map(|&(_, _, gap)| gap)
+                .reduce(f64::min)
+                .unwrap_or(1.0);
+            let [x, y, z] = axes.map(|(_, extent, gap)| extent + gap.unwrap_or(fallback));
+            let [(u, _, _), (v, _, _), (w, _, _)] = axes;
+            sites = sites
+                .into_iter()
+                .map(|site| {
+                    let (x, y, z) = site.position();
+                    site.with_position((x - u, y - v, z - w))
+                })
+                .collect();
+            Lattice::try_new((x, y, z))?
+        }
+    };
+    lattice.try_with_sites(sites)
+}
+
+/// Parses a logical value
+fn flag(token: &str) -> Option<bool> {
+    match token {
+        "T" | "True" | "true" | "1" => Some(true),
+        "F" | "False" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// Smallest of the given coordinates, their extent and the smallest gap
+/// between them, `None` when they all lay on the same plane
+fn extent(values: impl Iterator<Item = f64>) -> (f64, f64, Option<f64>) {
+    let mut values: Vec<_> = values.collect();
+    values.sort_by(f64::total_cmp);
+    let (min, extent) = match (values.first(), values.last()) {
+        (Some(first), Some(last)) => (*first, last - first),
+        _ => (0.0, 0.0),
+    };
+    let gap = values
+        .windows(2)
+        .map(|pair| pair[1] - pair[0])
+        .filter(|&gap| gap > GAP_TOLERANCE)
+        .reduce(f64::min);
+    (min, extent, gap)
+}
+
+/// Deserializes a lattice from a reader with an XYZ or extended XYZ file
+pub fn from_reader_xyz<R>(mut reader: R) -> Result<Lattice>
+where
+    R: Read,
+{
+    let mut source = String::new();
+    reader.read_to_string(&mut source)?;
+    from_str_xyz(&source)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{from_str_xyz, to_string_xyz, to_writer_xyz_supercell};
+    use crate::{Lattice, Site, Tagged};
+
+    #[test]
+    fn xyz_has_count_and_comment_lines() {
+        let data = to_string_xyz(&Lattice::bcc(2.0)).unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "2");
+        assert_eq!(
+            lines[1],
+            "Lattice=\"2 0 0 0 2 0 0 0 2\" Properties=species:S:1:pos:R:3 pbc=\"T T T\""
+        );
+        assert_eq!(lines[2], "A 0 0 0");
+        assert_eq!(lines[3], "B 1 1 1");
+    }
+
+    #[test]
+    fn xyz_reports_dropped_periodicity() {
+        let data = to_string_xyz(&Lattice::sc(1.0).drop_z()).unwrap();
+        assert!(data.contains("pbc=\"T T F\""));
+    }
+
+    #[test]
+    fn xyz_writes_tag_columns() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![
+                Site::new("Fe").with_tags(vec!["core"]),
+                Site::new("Fe").with_tags(vec!["shell", "core"]),
+                Site::new("Fe"),
+            ])
+            .unwrap();
+        let data = to_string_xyz(&lattice).unwrap();
+        let lines: Vec<_> = data.lines().collect();
+        assert!(lines[1].contains("Properties=species:S:1:pos:R:3:core:L:1:shell:L:1 "));
+        assert_eq!(lines[2], "Fe 0 0 0 T F");
+        assert_eq!(lines[3], "Fe 0 0 0 T T");
+        assert_eq!(lines[4], "Fe 0 0 0 F F");
+    }
+
+    #[test]
+    fn xyz_supercell_matches_expansion() {
+        let lattice = Lattice::hcp(1.0, 1.6).drop_z();
+        let mut data = Vec::new();
+        to_writer_xyz_supercell(&mut data, &lattice.supercell(2, 2, 3)).unwrap();
+        let expected = to_string_xyz(&lattice.expand(2, 2, 3)).unwrap();
+        assert_eq!(String::from_utf8(data).unwrap(), expected);
+    }
+
+    #[test]
+    fn xyz_can_be_read_back() {
+        let lattice = Lattice::hcp(1.0, 1.6)
+            .try_with_sites(vec![
+                Site::new("Co").with_tags(vec!["surface"]),
+                Site::new("Co").with_position((0.0, 0.5, 0.8)),
+            ])
+            .unwrap();
+        let data = to_string_xyz(&lattice).unwrap();
+        let output = from_str_xyz(&data).unwrap();
+        assert!((output.size().2 - 1.6).abs() < 1e-10);
+        assert!((output.vectors()[1].0 + 0.5).abs() < 1e-10);
+        assert_eq!(output.sites().len(), 2);
+        assert!(output.site(0).has_tag("surface"));
+        assert!(!output.site(1).has_tag("surface"));
+        assert_eq!(output.site(1).position(), (0.0, 0.5, 0.8));
+        assert!(output.edges().is_empty());
+    }
+
+    #[test]
+    fn xyz_encodes_kinds_and_tags() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![
+                Site::new("Fe 1:a").with_tags(vec!["in core", "50%"]),
+                Site::new("Ni"),
+            ])
+            .unwrap();
+        let data = to_string_xyz(&lattice).unwrap();
+        assert!(data.contains("Properties=species:S:1:pos:R:3:in%20core:L:1:50%25:L:1 "));
+        assert!(data.contains("\nFe%201%3Aa 0 0 0 T T\n"));
+        let output = from_str_xyz(&data).unwrap();
+        assert_eq!(output.site(0).kind(), "Fe 1:a");
+        assert_eq!(output.site(0).tags(), Some(vec!["in core", "50%"]));
+        assert_eq!(output.site(1).kind(), "Ni");
+        assert_eq!(
+            from_str_xyz("1\n\nFe%2 0 0 0\n").unwrap().site(0).kind(),
+            "Fe%2"
+        );
+    }
+
+    #[test]
+    fn xyz_reads_plain_files() {
+        let data = "3\nwater molecule\nO 0.0 0.0 0.0\nH 0.75 0.5 0.0\nH -0.75 0.5 0.0\n";
+        let lattice = from_str_xyz(data).unwrap();
+        assert_eq!(lattice.size(), (2.25, 1.0, 0.5));
+        assert_eq!(lattice.site(0).position(), (0.75, 0.0, 0.0));
+        assert_eq!(lattice.site(2).kind(), "H");
+        assert_eq!(lattice.site(2).position(), (0.0, 0.5, 0.0));
+    }
+
+    #[test]
+    fn xyz_fails_on_non_periodic_axes() {
+        let data = to_string_xyz(&Lattice::sc(1.0).drop_z()).unwrap();
+        let error = from_str_xyz(&data).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error at line 2: non periodic axes in pbc `T T F` are not supported"
+        );
+        let data = "1\npbc=\"T T\"\nFe 0 0 0\n";
+        assert!(from_str_xyz(data).is_err());
+    }
+
+    fn plain(lattice: &Lattice) -> String {
+        let mut data = format!("{}\n\n", lattice.sites().len());
+        for site in lattice.sites() {
+            let (x, y, z) = site.position();
+            data.push_str(&format!("{} {} {} {}\n", site.kind(), x, y, z));
+        }
+        data
+    }
+
+    #[test]
+    fn xyz_plain_files_keep_the_period_of_the_sites() {
+        let lattice = from_str_xyz(&plain(&Lattice::sc(1.0).expand_all(3))).unwrap();
+        assert_eq!(lattice.size(), (3.0, 3.0, 3.0));
+        assert_eq!(
+            lattice
+                .try_with_neighbors_within(1.1)
+                .unwrap()
+                .edges()
+                .len(),
+            81
+        );
+    }
+
+    #[test]
+    fn xyz_plain_planar_files_can_be_bonded() {
+        let lattice = from_str_xyz(&plain(&Lattice::sc(1.0).expand(3, 3, 1))).unwrap();
+        assert_eq!(lattice.size(), (3.0, 3.0, 1.0));
+        assert_eq!(
+            lattice
+                .try_with_neighbors_within(1.1)
+                .unwrap()
+                .edges()
+                .len(),
+            27
+        );
+        let single = from_str_xyz("1\n\nFe 0.5 0.5 0.5\n").unwrap();
+        assert_eq!(single.size(), (1.0, 1.0, 1.0));
+    }
+
+    #[test]
+    fn xyz_skips_unknown_columns() {
+        let data = "1
+            Properties=id:I:1:species:S:1:forces:R:3:pos:R:3 energy=-1.0 Lattice=\"2 0 0 0 2 0 0 0 2\"
+            7 Fe 0.1 0.2 0.3 0.5 0.5 0.5
+        ";
+        let lattice = from_str_xyz(data).unwrap();
+        assert_eq!(lattice.size(), (2.0, 2.0, 2.0));
+        assert_eq!(lattice.site(0).kind(), "Fe");
+        assert_eq!(lattice.site(0).position(), (0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn xyz_fails_on_missing_atoms() {
+        let data = "2\n\nFe 0 0 0\n";
+        let error = from_str_xyz(data).unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "parse error at line 4: unexpected end of file"
+        );
+    }
+}
@@ -1,41 +1,90 @@
 //! Lattice data structure
 
-use super::util::Axis;
+use super::util::{
+    Axis, IDENTITY, Vector, add, determinant, distinct, dot, norm, reciprocal, scale,
+};
 use crate::{
     alloy::Alloy,
     edge::Edge,
     error::{Result, VegasLatticeError},
+    graded::GradedAlloy,
     mask::Mask,
-    site::Site,
+    neighbor::{Neighbor, neighbor_shells, neighbors_within},
+    parallel::{count_before, filter_map_indexed, map_range, map_with_rng},
+    selector::Selector,
+    site::{Record, Site, SiteRef, SiteTable, Sites},
+    sro::{PairCounts, shell, warren_cowley, warren_cowley_tagged},
+    supercell::Supercell,
 };
-use rand::Rng;
+use rand::{Rng, rngs::StdRng, seq::SliceRandom};
 use serde::{Deserialize, Serialize};
-use std::{iter::repeat_n, str::FromStr};
+use std::{collections::BTreeMap, str::FromStr};
 
 /// A lattice is a collection of sites and edges.
 ///
-/// For now it only supports rectangular lattices. This is Orthorombic,
-/// Tetragonal and Cubic Bravais lattices. We assume the lattice vectors are
-/// aligned with the cartesian axes. While you can choose the lattice parameters
-/// _a_, _b_, and _c_ to be different.
+/// The `size` holds the lattice parameters _a_, _b_, and _c_, while `vectors`
+/// holds the directions of the three lattice vectors, so the actual cell
+/// vectors are `size.0 * vectors[0]`, `size.1 * vectors[1]` and
+/// `size.2 * vectors[2]`. This is the same convention used by the Vampire
+/// unit cell files.
+///
+/// By default the lattice vectors are aligned with the cartesian axes, which
+/// covers the Orthorombic, Tetragonal and Cubic Bravais lattices. Other cells,
+/// like hexagonal, monoclinic or triclinic ones, can be described by changing
+/// the lattice vectors. Files without `vectors` are read as orthogonal cells.
+///
+/// The `metadata` holds free form information about how the lattice was
+/// made, like the seeds used to alloy it, and it is omitted when empty.
+///
+/// Every kind is stored once in a table held by the lattice, and the sites
+/// refer to it by index, so they are cheap to copy when expanding or
+/// alloying.
+///
+/// # Examples
+///
+/// ```rust
+/// use vegas_lattice::Lattice;
+///
+/// let half = 3.0_f64.sqrt() / 2.0;
+/// let lattice = Lattice::sc(1.0)
+///     .try_with_vectors([(1.0, 0.0, 0.0), (-0.5, half, 0.0), (0.0, 0.0, 1.0)])
+///     .unwrap()
+///     .expand_y(2);
+///
+/// let (x, y, _) = lattice.site(1).position();
+/// assert!((x + 0.5).abs() < 1e-10);
+/// assert!((y - half).abs() < 1e-10);
+/// ```
 #[derive(Clone, Debug, Serialize, Deserialize)]
 pub struct Lattice {
     size: (f64, f64, f64),
-    sites: Vec<Site>,
+    #[serde(default = "default_vectors", skip_serializing_if = "is_orthogonal")]
+    vectors: [Vector; 3],
+    sites: SiteTable,
     edges: Vec<Edge>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    metadata: BTreeMap<String, String>,
+}
+
+fn default_vectors() -> [Vector; 3] {
+    IDENTITY
+}
+
+fn is_orthogonal(vectors: &[Vector; 3]) -> bool {
+    *vectors == IDENTITY
 }
 
 impl Lattice {
     /// Create a new lattice with the given size
     pub fn try_new(size: (f64, f64, f64)) -> Result<Self> {
-        if size.0 < 0.0 || size.1 < 0.0 || size.2 < 0.0 {
-            return Err(VegasLatticeError::NegativeSize);
-        }
-        Ok(Lattice {
+        Lattice {
             size,
-            sites: Vec::new(),
+            vectors: IDENTITY,
+            sites: SiteTable::default(),
             edges: Vec::new(),
-        })
+            metadata: BTreeMap::new(),
+        }
+        .validate()
     }
 
     /// Create a simple cubic lattice with the given size _a_
@@ -48,8 +97,10 @@ impl Lattice {
         ];
         Lattice {
             size: (a, a, a),
-            sites,
+            vectors: IDENTITY,
+            sites: sites.into_iter().collect(),
             edges,
+            metadata: BTreeMap::new(),
         }
     }
 
@@ -71,8 +122,10 @@ impl Lattice {
         ];
         Lattice {
             size: (a, a, a),
-            sites,
+            vectors: IDENTITY,
+            sites: sites.into_iter().collect(),
             edges,
+            metadata: BTreeMap::new(),
         }
     }
 
@@ -103,8 +156,53 @@ impl Lattice {
         ];
         Lattice {
             size: (a, a, a),
-            sites,
+            vectors: IDENTITY,
+            sites: sites.into_iter().collect(),
+            edges,
+            metadata: BTreeMap::new(),
+        }
+    }
+
+    /// Create a hexagonal close packed lattice with lattice parameters _a_ and _c_
+    ///
+    /// The lattice vectors are _a_ (1, 0, 0), _a_ (-1/2, √3/2, 0) and _c_ (0, 0, 1),
+    /// the second site sits at (1/3, 2/3, 1/2) in fractional coordinates and
+    /// the edges connect every site to its 12 nearest neighbours in the ideal
+    /// case _c_ = √(8/3) _a_.
+    pub fn hcp(a: f64, c: f64) -> Self {
+        let vectors = [
+            (1.0, 0.0, 0.0),
+            (-0.5, 0.5 * 3.0_f64.sqrt(), 0.0),
+            (0.0, 0.0, 1.0),
+        ];
+        let sites = vec![
+            Site::new("A"),
+            Site::new("B").with_position((0.0, a / 3.0_f64.sqrt(), 0.5 * c)),
+        ];
+        let edges = vec![
+            // basal plane of the A sublattice
+            Edge::new(0, 0, (1, 0, 0)),
+            Edge::new(0, 0, (0, 1, 0)),
+            Edge::new(0, 0, (1, 1, 0)),
+            // basal plane of the B sublattice
+            Edge::new(1, 1, (1, 0, 0)),
+            Edge::new(1, 1, (0, 1, 0)),
+            Edge::new(1, 1, (1, 1, 0)),
+            // B sites above the A plane
+            Edge::new(0, 1, (0, 0, 0)),
+            Edge::new(0, 1, (0, -1, 0)),
+            Edge::new(0, 1, (-1, -1, 0)),
+            // B sites below the A plane
+            Edge::new(0, 1, (0, 0, -1)),
+            Edge::new(0, 1, (0, -1, -1)),
+            Edge::new(0, 1, (-1, -1, -1)),
+        ];
+        Lattice {
+            size: (a, a, c),
+            vectors,
+            sites: sites.into_iter().collect(),
             edges,
+            metadata: BTreeMap::new(),
         }
     }
 
@@ -113,9 +211,81 @@ impl Lattice {
         self.size
     }
 
+    /// Get the directions of the lattice vectors
+    pub fn vectors(&self) -> [(f64, f64, f64); 3] {
+        self.vectors
+    }
+
+    /// Get the cell vectors, this is, the lattice vectors scaled by the size
+    pub fn cell(&self) -> [(f64, f64, f64); 3] {
+        [
+            scale(self.vectors[0], self.size.0),
+            scale(self.vectors[1], self.size.1),
+            scale(self.vectors[2], self.size.2),
+        ]
+    }
+
+    /// Get the reciprocal vectors of the cell, fails when a size is zero and
+    /// the cell has no volume
+    pub(crate) fn reciprocal_cell(&self) -> Result<[Vector; 3]> {
+        let cell = self.cell();
+        if determinant(&cell).abs() < f64::EPSILON {
+            return Err(VegasLatticeError::ZeroVolume);
+        }
+        Ok(reciprocal(&cell))
+    }
+
+    /// Get the fractional coordinates of a position in terms of the cell
+    /// vectors, fails when the cell has no volume
+    pub fn to_fractional(&self, position: (f64, f64, f64)) -> Result<(f64, f64, f64)> {
+        let reciprocal = self.reciprocal_cell()?;
+        Ok((
+            dot(position, reciprocal[0]),
+            dot(position, reciprocal[1]),
+            dot(position, reciprocal[2]),
+        ))
+    }
+
+    /// Get the cartesian position of the given fractional coordinates
+    pub fn to_cartesian(&self, fractional: (f64, f64, f64)) -> (f64, f64, f64) {
+        let cell = self.cell();
+        add(
+            add(scale(cell[0], fractional.0), scale(cell[1], fractional.1)),
+            scale(cell[2], fractional.2),
+        )
+    }
+
     /// Get the sites of the lattice
-    pub fn sites(&self) -> &[Site] {
-        &self.sites
+    pub fn sites(&self) -> Sites<'_> {
+        self.sites.view()
+    }
+
+    /// Get the site at the given index
+    ///
+    /// # Panics
+    ///
+    /// If the index is out of bounds.
+    pub fn site(&self, index: usize) -> SiteRef<'_> {
+        self.sites.get(index)
+    }
+
+    /// Get the metadata of the lattice
+    pub fn metadata(&self) -> &BTreeMap<String, String> {
+        &self.metadata
+    }
+
+    /// Sets a metadata entry, replacing any previous value
+    pub fn with_metadata(mut self, key: &str, value: &str) -> Self {
+        self.metadata.insert(key.to_string(), value.to_string());
+        self
+    }
+
+    /// Get the distinct kinds of the sites, in order of first appearance
+    pub fn kinds(&self) -> Vec<&str> {
+        distinct(self.sites.records.iter().map(|record| record.kind))
+            .into_iter()
+            .map(|kind| self.sites.kinds[kind as usize].as_str())
+            .collect()
     }
 
     /// Get the edges of the lattice
@@ -129,9 +299,35 @@ impl Lattice {
         self.validate()
     }
 
+    /// Changes the directions of the lattice vectors
+    pub fn try_with_vectors(mut self, vectors: [(f64, f64, f64); 3]) -> Result<Self> {
+        self.vectors = vectors;
+        self.validate()
+    }
+
+    /// Changes both the size and the lattice vectors to match the given cell
+    /// vectors, the size becomes the length of each cell vector.
+    pub fn try_with_cell(mut self, cell: [(f64, f64, f64); 3]) -> Result<Self> {
+        for (i, vector) in cell.into_iter().enumerate() {
+            let length = norm(vector);
+            let direction = if length > 0.0 {
+                (vector.0 / length, vector.1 / length, vector.2 / length)
+            } else {
+                vector
+            };
+            match i {
+                0 => self.size.0 = length,
+                1 => self.size.1 = length,
+                _ => self.size.2 = length,
+            }
+            self.vectors[i] = direction;
+        }
+        self.validate()
+    }
+
     /// Changes the sites of the lattice
     pub fn try_with_sites(mut self, sites: Vec<Site>) -> Result<Self> {
-        self.sites = sites;
+        self.sites = sites.into_iter().collect();
         self.validate()
     }
 
@@ -150,13 +346,16 @@ impl Lattice {
     }
 
     /// Validates the lattice
-    fn validate(self) -> Result<Self> {
+    pub(crate) fn validate(self) -> Result<Self> {
         if !self.are_edges_consistent() {
             return Err(VegasLatticeError::InconsistentEdges);
         }
         if self.size.0 < 0.0 || self.size.1 < 0.0 || self.size.2 < 0.0 {
             return Err(VegasLatticeError::NegativeSize);
         }
+        if determinant(&self.vectors).abs() < f64::EPSILON {
+            return Err(VegasLatticeError::DegenerateVectors);
+        }
         Ok(self)
     }
 
@@ -202,31 +401,40 @@ impl Lattice {
         }
     }
 
+    #[inline]
+    fn vector_along(&self, axis: Axis) -> Vector {
+        match axis {
+            Axis::X => self.vectors[0],
+            Axis::Y => self.vectors[1],
+            Axis::Z => self.vectors[2],
+        }
+    }
+
     /// Expands the lattice along the given axis
     fn expand_along(mut self, axis: Axis, amount: usize) -> Self {
         let size = self.size_along(axis);
+        let vector = self.vector_along(axis);
         let n_sites = self.sites.len();
         let n_edges = self.edges.len();
 
-        self.sites = (0..amount)
-            .flat_map(|i| repeat_n(i, n_sites))
-            .zip(self.sites().iter().cycle())
-            .map(|(index, site)| match axis {
-                Axis::X => site.clone().move_x((index as f64) * size),
-                Axis::Y => site.clone().move_y((index as f64) * size),
-                Axis::Z => site.clone().move_z((index as f64) * size),
-            })
-            .collect();
+        let records = &self.sites.records;
+        let records = map_range(amount * n_sites, |i| {
+            let (index, basis) = (i / n_sites, i % n_sites);
+            records[basis].moved(vector, (index as f64) * size, basis)
+        });
 
-        self.edges = (0..amount)
-            .flat_map(|i| repeat_n(i, n_edges))
-            .zip(self.edges.iter().cycle())
-            .map(|(index, edge)| match axis {
-                Axis::X => edge.clone().move_x(index, n_sites, amount),
-                Axis::Y => edge.clone().move_y(index, n_sites, amount),
-                Axis::Z => edge.clone().move_z(index, n_sites, amount),
-            })
-            .collect();
+        let edges = &self.edges;
+        let edges = map_range(amount * n_edges, |i| {
+            let (index, edge) = (i / n_edges, edges[i % n_edges].clone());
+            match axis {
+                Axis::X => edge.move_x(index, n_sites, amount),
+                Axis::Y => edge.move_y(index, n_sites, amount),
+                Axis::Z => edge.move_z(index, n_sites, amount),
+            }
+        });
+
+        self.sites.records = records;
+        self.edges = edges;
 
         match axis {
             Axis::X => self.size.0 *= amount as f64,
@@ -262,41 +470,26 @@ impl Lattice {
         self.expand_x(x).expand_y(y).expand_z(z)
     }
 
+    /// Lazy version of `expand`, the sites and edges of the expanded lattice
+    /// are computed as they are needed
+    pub fn supercell(&self, x: usize, y: usize, z: usize) -> Supercell<'_> {
+        Supercell::new(self, x, y, z)
+    }
+
     /// Removes sites from the lattice according to the given mask and
     /// perpendicular to the given axis.
     fn apply_mask<R: Rng>(mut self, mask: Mask, axis: Axis, rng: &mut R) -> Self {
-        let site_mask: Vec<_> = self
-            .sites
-            .iter()
-            .map(|s| {
-                let (x, y) = axis.project_in_plane(s.position());
-                mask.keep(x, y, rng)
-            })
-            .collect();
-        let mut counter = 0;
-        let new_indices: Vec<_> = (0..self.sites.len())
-            .map(|i| {
-                if site_mask[i] {
-                    counter += 1;
-                    counter - 1
-                } else {
-                    i
-                }
-            })
-            .collect();
-        self.sites = self
-            .sites
-            .into_iter()
-            .enumerate()
-            .filter(|&(i, ref _s)| site_mask[i])
-            .map(|(_i, s)| s)
-            .collect();
-        self.edges = self
-            .edges
-            .into_iter()
-            .filter(|v| site_mask[v.source()] && site_mask[v.target()])
-            .map(|v| v.reindex(&new_indices))
-            .collect();
+        let site_mask = map_with_rng(&self.sites.records, rng.next_u64(), |s, rng| {
+            let (x, y) = axis.project_in_plane(s.position);
+            mask.keep(x, y, rng)
+        });
+        // Kept sites move to the number of kept sites before them
+        let new_indices = count_before(&site_mask);
+        let records = std::mem::take(&mut self.sites.records);
+        self.sites.records = filter_map_indexed(records, |i, s| site_mask[i].then_some(s));
+        self.edges = filter_map_indexed(self.edges, |_, v| {
+            (site_mask[v.source()] && site_mask[v.target()]).then(|| v.reindex(&new_indices))
+        });
         self
     }
 
@@ -315,21 +508,381 @@ impl Lattice {
         self.apply_mask(mask, Axis::Z, rng)
     }
 
-    /// Replaces the sites labeled as `source` with sites in the `target` alloy
-    pub fn alloy_sites<R: Rng>(mut self, source: &str, target: Alloy, rng: &mut R) -> Self {
-        self.sites = self
-            .sites
-            .into_iter()
-            .map(|site| {
-                if site.kind() != source {
-                    site
-                } else {
-                    site.with_kind(target.pick(rng))
-                }
-            })
+    /// Replaces the edges of the lattice with the ones joining every pair of
+    /// sites closer than `cutoff`.
+    ///
+    /// The periodic images of the lattice are scanned, so pairs of sites in
+    /// neighboring cells get the appropriate `delta`, and every pair is
+    /// included only once. Fails when the cell has no volume, since a zero
+    /// size leaves no room between the periodic images.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use vegas_lattice::Lattice;
+    ///
+    /// let lattice = Lattice::bcc(1.0).try_with_neighbors_within(0.9).unwrap();
+    /// assert_eq!(lattice.edges().len(), 8);
+    /// ```
+    pub fn try_with_neighbors_within(mut self, cutoff: f64) -> Result<Self> {
+        self.edges = neighbors_within(&self, cutoff, &|_, _| true)?
+            .iter()
+            .map(|neighbor| neighbor.edge())
+            .collect();
+        Ok(self)
+    }
+
+    /// Same as `try_with_neighbors_within` but only joins sites whose kinds
+    /// match one of the given pairs of kinds, in any order.
+    pub fn try_with_neighbors_within_kinds(
+        mut self,
+        cutoff: f64,
+        kinds: &[(&str, &str)],
+    ) -> Result<Self> {
+        self.edges = neighbors_within(&self, cutoff, &kinds_filter(kinds))?
+            .iter()
+            .map(|neighbor| neighbor.edge())
             .collect();
+        Ok(self)
+    }
+
+    /// Replaces the edges of the lattice with the ones joining every site to
+    /// its neighbors up to the given number of `shells`.
+    ///
+    /// Edges are tagged with the shell they belong to, `nn1` for the nearest
+    /// neighbors, `nn2` for the next nearest neighbors, and so on. Fails when
+    /// the cell has no volume.
+    ///
+    /// # Examples
+    ///
+    /// ```rust
+    /// use vegas_lattice::{Lattice, Tagged};
+    ///
+    /// let lattice = Lattice::bcc(1.0).try_with_neighbor_shells(2).unwrap();
+    /// let nn1 = lattice.edges().iter().filter(|e| e.has_tag("nn1")).count();
+    /// let nn2 = lattice.edges().iter().filter(|e| e.has_tag("nn2")).count();
+    /// assert_eq!(nn1, 8);
+    /// assert_eq!(nn2, 6);
+    /// ```
+    pub fn try_with_neighbor_shells(mut self, shells: usize) -> Result<Self> {
+        self.edges = shell_edges(neighbor_shells(&self, shells, |_, _| true)?);
+        Ok(self)
+    }
+
+    /// Same as `try_with_neighbor_shells` but only joins sites whose kinds
+    /// match one of the given pairs of kinds, in any order.
+    pub fn try_with_neighbor_shells_kinds(
+        mut self,
+        shells: usize,
+        kinds: &[(&str, &str)],
+    ) -> Result<Self> {
+        self.edges = shell_edges(neighbor_shells(&self, shells, kinds_filter(kinds))?);
+        Ok(self)
+    }
+
+    /// Replaces the sites labeled as `source` with sites in the `target` alloy
+    pub fn alloy_sites<R: Rng>(self, source: &str, target: Alloy, rng: &mut R) -> Self {
+        self.alloy_sites_where(&Selector::Kind(source.to_string()), target, rng)
+    }
+
+    /// Replaces the sites chosen by the `selector` with sites in the `target`
+    /// alloy
+    pub fn alloy_sites_where<R: Rng>(
+        mut self,
+        selector: &Selector,
+        target: Alloy,
+        rng: &mut R,
+    ) -> Self {
+        let kinds = self.intern_kinds(target.kinds());
+        self.substitute(selector, rng, |record, rng| {
+            let index = target.pick_index(rng);
+            let mut record = record.clone();
+            record.kind = kinds[index];
+            record.add_tags(target.tags(index));
+            record
+        })
+    }
+
+    /// Same as `alloy_sites` but the ratios depend on the position of each
+    /// site, following the gradient and profile of the alloy.
+    pub fn alloy_sites_graded<R: Rng>(
+        self,
+        source: &str,
+        target: GradedAlloy,
+        rng: &mut R,
+    ) -> Self {
+        self.alloy_sites_graded_where(&Selector::Kind(source.to_string()), target, rng)
+    }
+
+    /// Same as `alloy_sites_graded` but for the sites chosen by the `selector`
+    pub fn alloy_sites_graded_where<R: Rng>(
+        mut self,
+        selector: &Selector,
+        target: GradedAlloy,
+        rng: &mut R,
+    ) -> Self {
+        let kinds = self.intern_kinds(target.kinds());
+        self.substitute(selector, rng, |record, rng| {
+            let mut record = record.clone();
+            record.kind = kinds[target.pick_index(record.position, rng)];
+            record
+        })
+    }
+
+    /// Indices of the given kinds in the kind table, adding the missing ones
+    fn intern_kinds(&mut self, kinds: &[String]) -> Vec<u32> {
+        kinds.iter().map(|kind| self.sites.intern(kind)).collect()
+    }
+
+    /// Replaces the sites chosen by the `selector` with the site returned by
+    /// `pick`
+    fn substitute<R, F>(mut self, selector: &Selector, rng: &mut R, pick: F) -> Self
+    where
+        R: Rng,
+        F: Fn(&Record, &mut StdRng) -> Record + Sync + Send,
+    {
+        let sites = &self.sites;
+        let indices: Vec<_> = (0..sites.len()).collect();
+        let substitutes = map_with_rng(&indices, rng.next_u64(), |&i, rng| {
+            selector
+                .matches(sites.get(i))
+                .then(|| pick(&sites.records[i], rng))
+        });
+        for (record, substitute) in self.sites.records.iter_mut().zip(substitutes) {
+            if let Some(substitute) = substitute {
+                *record = substitute;
+            }
+        }
+        self
+    }
+
+    /// Indices of the sites chosen by the `selector`
+    fn select(&self, selector: &Selector) -> Vec<usize> {
+        (0..self.sites.len())
+            .filter(|&i| selector.matches(self.sites.get(i)))
+            .collect()
+    }
+
+    /// Same as `alloy_sites` but the number of sites of each kind follows the
+    /// ratios of the alloy exactly, as given by `Alloy::counts`, only which
+    /// sites get each kind is random.
+    pub fn alloy_sites_exact<R: Rng>(self, source: &str, target: Alloy, rng: &mut R) -> Self {
+        self.alloy_sites_exact_where(&Selector::Kind(source.to_string()), target, rng)
+    }
+
+    /// Same as `alloy_sites_exact` but for the sites chosen by the `selector`
+    pub fn alloy_sites_exact_where<R: Rng>(
+        mut self,
+        selector: &Selector,
+        target: Alloy,
+        rng: &mut R,
+    ) -> Self {
+        let candidates = self.select(selector);
+        let kinds = self.assign_exact(&candidates, &target, rng);
+        self.tag_alloyed(&candidates, &kinds, &target);
+        self
+    }
+
+    /// Gives the `candidates` the kinds of the alloy in exact proportions,
+    /// leaving their tags alone, returns the indices of the kinds of the
+    /// alloy in the kind table
+    fn assign_exact<R: Rng>(
+        &mut self,
+        candidates: &[usize],
+        target: &Alloy,
+        rng: &mut R,
+    ) -> Vec<u32> {
+        let kinds = self.intern_kinds(target.kinds());
+        let mut indices = candidates.to_vec();
+        indices.shuffle(rng);
+        let picks = target.exact_indices(indices.len());
+        for (index, pick) in indices.into_iter().zip(picks) {
+            self.sites.records[index].kind = kinds[pick];
+        }
+        kinds
+    }
+
+    /// Adds to the given sites the tags of their kind in the alloy, `kinds`
+    /// are the indices of the kinds of the alloy in the kind table
+    fn tag_alloyed(&mut self, sites: &[usize], kinds: &[u32], target: &Alloy) {
+        for &i in sites.iter() {
+            let record = &mut self.sites.records[i];
+            if let Some(index) = kinds.iter().position(|&kind| kind == record.kind) {
+                record.add_tags(target.tags(index));
+            }
+        }
+    }
+
+    /// Same as `alloy_sites_exact` but then swaps the kinds of the alloyed
+    /// sites, for at most `steps` trials, to approach the target Warren–Cowley
+    /// parameters given as `(i, j, alpha)` triplets. The parameters are those
+    /// of the alloyed sites alone, every edge between two of them counts as a
+    /// pair of neighbors and the concentrations are taken over them.
+    ///
+    /// Fails if a kind of the targets is not found among the alloyed sites.
+    pub fn try_alloy_sites_ordered<R: Rng>(
+        self,
+        source: &str,
+        target: Alloy,
+        order: &[(&str, &str, f64)],
+        steps: usize,
+        rng: &mut R,
+    ) -> Result<Self> {
+        let selector = Selector::Kind(source.to_string());
+        self.try_alloy_sites_ordered_where(&selector, target, order, steps, rng)
+    }
+
+    /// Same as `try_alloy_sites_ordered` but for the sites chosen by the
+    /// `selector`
+    pub fn try_alloy_sites_ordered_where<R: Rng>(
+        mut self,
+        selector: &Selector,
+        target: Alloy,
+        order: &[(&str, &str, f64)],
+        steps: usize,
+        rng: &mut R,
+    ) -> Result<Self> {
+        let candidates = self.select(selector);
+        let kinds = self.assign_exact(&candidates, &target, rng);
+        let mut pairs = self.pairs_among(&candidates, 1, |_| Some(0));
+        let order = pairs.targets(order)?;
+        pairs.minimize(&candidates, steps, rng, |pairs| pairs.error(&order));
+        Ok(self.with_kinds_of(&pairs, &candidates, &kinds, &target))
+    }
+
+    /// Builds a special quasirandom structure, like `alloy_sites_exact` but
+    /// then swaps the kinds of the alloyed sites, for at most `steps` trials,
+    /// so that their pair correlations match those of a random alloy as
+    /// closely as possible.
+    ///
+    /// Every neighbor shell tagged by `try_with_neighbor_shells` is matched, the
+    /// closer ones weighing more, or every edge as a single shell if there
+    /// are no such tags. Only the pairs of alloyed sites are taken into
+    /// account.
+    ///
+    /// ```rust
+    /// use rand::{SeedableRng, rngs::StdRng};
+    /// use vegas_lattice::{Alloy, Lattice};
+    ///
+    /// let alloy = Alloy::try_new(vec!["Fe", "Ni"], vec![1, 1]).unwrap();
+    /// let lattice = Lattice::sc(1.0)
+    ///     .expand_all(4)
+    ///     .try_with_neighbor_shells(2).unwrap()
+    ///     .alloy_sites_sqs("A", alloy, 10000, &mut StdRng::seed_from_u64(1));
+    ///
+    /// for (_, _, alpha) in lattice.warren_cowley_tagged("nn1") {
+    ///     assert!(alpha.abs() < 0.1);
+    /// }
+    /// ```
+    pub fn alloy_sites_sqs<R: Rng>(
+        self,
+        source: &str,
+        target: Alloy,
+        steps: usize,
+        rng: &mut R,
+    ) -> Self {
+        let selector = Selector::Kind(source.to_string());
+        self.alloy_sites_sqs_where(&selector, target, steps, rng)
+    }
+
+    /// Same as `alloy_sites_sqs` but for the sites chosen by the `selector`
+    pub fn alloy_sites_sqs_where<R: Rng>(
+        mut self,
+        selector: &Selector,
+        target: Alloy,
+        steps: usize,
+        rng: &mut R,
+    ) -> Self {
+        let candidates = self.select(selector);
+        let kinds = self.assign_exact(&candidates, &target, rng);
+        let shells = self.edges.iter().filter_map(shell).max();
+        let mut pairs =
+            self.pairs_among(
+                &candidates,
+                shells.map_or(1, |s| s + 1),
+                |edge| match shells {
+                    Some(_) => shell(edge),
+                    None => Some(0),
+                },
+            );
+        let composition = pairs.composition(&candidates);
+        pairs.minimize(&candidates, steps, rng, |pairs| {
+            pairs.disorder(&composition)
+        });
+        self.with_kinds_of(&pairs, &candidates, &kinds, &target)
+    }
+
+    /// Counts the pairs of neighbors among the given sites only, see
+    /// `PairCounts::new`, the concentrations are taken over those sites too
+    fn pairs_among<F>(&self, sites: &[usize], groups: usize, group: F) -> PairCounts
+    where
+        F: Fn(&Edge) -> Option<usize>,
+    {
+        let mut among = vec![false; self.sites.len()];
+        for &i in sites.iter() {
+            among[i] = true;
+        }
+        PairCounts::new(self, groups, |edge| {
+            if among[edge.source()] && among[edge.target()] {
+                group(edge)
+            } else {
+                None
+            }
+        })
+        .within(sites)
+    }
+
+    /// Gives the given sites the kinds they have in the pair counts, along
+    /// with the tags of those kinds in the alloy
+    fn with_kinds_of(
+        mut self,
+        pairs: &PairCounts,
+        sites: &[usize],
+        kinds: &[u32],
+        target: &Alloy,
+    ) -> Self {
+        for &i in sites.iter() {
+            self.sites.records[i].kind = pairs.sites()[i] as u32;
+        }
+        self.tag_alloyed(sites, kinds, target);
         self
     }
+
+    /// Get the table of kinds along with the sites that refer to it
+    pub(crate) fn site_table(&self) -> &SiteTable {
+        &self.sites
+    }
+
+    /// Computes the Warren–Cowley short-range order parameters `α_ij` for
+    /// every pair of kinds, using every edge as a pair of neighbors.
+    ///
+    /// Pairs of kinds where the first kind has no neighbors are left out.
+    pub fn warren_cowley(&self) -> Vec<(String, String, f64)> {
+        warren_cowley(self, |_| true)
+    }
+
+    /// Same as `warren_cowley` but only the edges with the given tag are
+    /// used, for instance `nn1` for the nearest neighbors
+    pub fn warren_cowley_tagged(&self, tag: &str) -> Vec<(String, String, f64)> {
+        warren_cowley_tagged(self, tag)
+    }
+}
+
+/// Builds a filter that accepts pairs of sites matching any of the given kinds
+fn kinds_filter<'a>(kinds: &'a [(&str, &str)]) -> impl Fn(SiteRef, SiteRef) -> bool + 'a {
+    move |source, target| {
+        kinds.iter().any(|&(a, b)| {
+            (source.kind() == a && target.kind() == b) || (source.kind() == b && target.kind() == a)
+        })
+    }
+}
+
+/// Turns neighbors into edges tagged with their shell
+fn shell_edges(neighbors: Vec<(Neighbor, usize)>) -> Vec<Edge> {
+    neighbors
+        .iter()
+        .map(|(neighbor, shell)| neighbor.edge().with_tags(vec![&format!("nn{}", shell)]))
+        .collect()
 }
 
 impl FromStr for Lattice {
@@ -342,7 +895,11 @@ impl FromStr for Lattice {
 
 #[cfg(test)]
 mod test {
-    use crate::{Edge, Lattice, Site};
+    use crate::{
+        Alloy, AlloySpec, Edge, Exchange, GradedAlloy, Gradient, Lattice, Mask, Selector, Site,
+        Tagged, TargetSpec, error::VegasLatticeError,
+    };
+    use rand::{SeedableRng, rngs::StdRng};
 
     #[test]
     fn drop_example() {
@@ -363,7 +920,7 @@ mod test {
         let lattice = Lattice::sc(1.0);
         let output = lattice.expand_x(2);
         assert_eq!(output.sites.len(), 2);
-        assert!((output.sites[1].position().0 - 1.0).abs() < 1e-10);
+        assert!((output.site(1).position().0 - 1.0).abs() < 1e-10);
     }
 
     #[test]
@@ -372,9 +929,9 @@ mod test {
         let lattice = lattice.expand_x(2);
         let output = lattice.expand_x(2);
         assert_eq!(output.sites.len(), 4);
-        assert!((output.sites[1].position().0 - 1.0).abs() < 1e-10);
-        assert!((output.sites[2].position().0 - 2.0).abs() < 1e-10);
-        assert!((output.sites[3].position().0 - 3.0).abs() < 1e-10);
+        assert!((output.site(1).position().0 - 1.0).abs() < 1e-10);
+        assert!((output.site(2).position().0 - 2.0).abs() < 1e-10);
+        assert!((output.site(3).position().0 - 3.0).abs() < 1e-10);
     }
 
     #[test]
@@ -407,6 +964,60 @@ mod test {
         assert_eq!(output.edges[1].delta().0, 0);
     }
 
+    #[test]
+    fn single_lattice_expansion_by_one_keeps_edges_consistent() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_edges(vec![Edge::new(0, 0, (1, -1, 0))])
+            .unwrap();
+        let output = lattice.expand(2, 1, 1);
+        assert_eq!(output.edges.len(), 2);
+        assert_eq!(output.edges[0].target(), 1);
+        assert_eq!(output.edges[0].delta(), (0, -1, 0));
+        assert_eq!(output.edges[1].target(), 0);
+        assert_eq!(output.edges[1].delta(), (1, -1, 0));
+    }
+
+    #[test]
+    fn test_with_neighbors_within() {
+        let lattice = Lattice::fcc(1.0).try_with_neighbors_within(0.8).unwrap();
+        assert_eq!(lattice.edges().len(), 24);
+    }
+
+    #[test]
+    fn test_with_neighbors_within_kinds() {
+        let lattice = Lattice::bcc(1.0)
+            .try_with_neighbors_within_kinds(1.1, &[("A", "A")])
+            .unwrap();
+        assert_eq!(lattice.edges().len(), 3);
+        assert!(lattice.edges().iter().all(|e| e.source() == 0));
+    }
+
+    #[test]
+    fn test_with_neighbor_shells_kinds() {
+        let lattice = Lattice::bcc(1.0)
+            .try_with_neighbor_shells_kinds(1, &[("A", "A")])
+            .unwrap();
+        assert_eq!(lattice.edges().len(), 3);
+        assert!(lattice.edges().iter().all(|e| e.has_tag("nn1")));
+    }
+
+    #[test]
+    fn expansion_keeps_the_exchange() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_edges(vec![
+                Edge::new(0, 0, (1, 0, 0)).with_exchange(Exchange::Isotropic(2.0)),
+            ])
+            .unwrap();
+        let output = lattice.expand_all(2);
+        assert_eq!(output.edges.len(), 8);
+        assert!(
+            output
+                .edges
+                .iter()
+                .all(|e| e.exchange() == Some(Exchange::Isotropic(2.0)))
+        );
+    }
+
     #[test]
     fn test_sc_lattice() {
         let lattice = Lattice::sc(1.0);
@@ -431,6 +1042,39 @@ mod test {
         assert_eq!(lattice.edges().len(), 12)
     }
 
+    #[test]
+    fn test_hcp_lattice() {
+        let lattice = Lattice::hcp(1.0, 1.6);
+        assert_eq!(lattice.size(), (1.0, 1.0, 1.6));
+        assert_eq!(lattice.sites().len(), 2);
+        assert_eq!(lattice.edges().len(), 12)
+    }
+
+    #[test]
+    fn test_hcp_edges_are_nearest_neighbours() {
+        let c = (8.0_f64 / 3.0).sqrt();
+        let lattice = Lattice::hcp(1.0, c);
+        let cell = lattice.cell();
+        for edge in lattice.edges() {
+            let source = lattice.site(edge.source()).position();
+            let target = lattice.site(edge.target()).position();
+            let (dx, dy, dz) = edge.delta();
+            let offset = (0..3).fold((0.0, 0.0, 0.0), |acc, i| {
+                let n = [dx, dy, dz][i] as f64;
+                (
+                    acc.0 + n * cell[i].0,
+                    acc.1 + n * cell[i].1,
+                    acc.2 + n * cell[i].2,
+                )
+            });
+            let distance = ((target.0 + offset.0 - source.0).powi(2)
+                + (target.1 + offset.1 - source.1).powi(2)
+                + (target.2 + offset.2 - source.2).powi(2))
+            .sqrt();
+            assert!((distance - 1.0).abs() < 1e-10);
+        }
+    }
+
     #[test]
     fn test_wit_size() {
         let lattice = Lattice::sc(1.0).try_with_size((2.0, 2.0, 2.0)).unwrap();
@@ -445,6 +1089,301 @@ mod test {
         assert_eq!(lattice.sites().len(), 2);
     }
 
+    #[test]
+    fn test_sites_share_their_kinds() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![Site::new("Fe"), Site::new("Ni"), Site::new("Fe")])
+            .unwrap();
+        assert_eq!(lattice.kinds(), vec!["Fe", "Ni"]);
+        let expanded = lattice.expand_x(2);
+        let table = expanded.site_table();
+        assert_eq!(table.kinds, vec!["Fe".to_string(), "Ni".to_string()]);
+        let kinds: Vec<_> = table.records.iter().map(|record| record.kind).collect();
+        assert_eq!(kinds, vec![0, 1, 0, 0, 1, 0]);
+        assert_eq!(expanded.site(4).kind(), "Ni");
+    }
+
+    #[test]
+    fn test_read_sites_share_their_kinds() {
+        let lattice: Lattice = r#"{
+            "size": [1.0, 1.0, 1.0],
+            "sites": [
+                {"kind": "Fe", "position": [0.0, 0.0, 0.0]},
+                {"kind": "Fe", "position": [0.5, 0.5, 0.5]}
+            ],
+            "edges": []
+        }"#
+        .parse()
+        .unwrap();
+        assert_eq!(lattice.site_table().kinds, vec!["Fe".to_string()]);
+        assert_eq!(lattice.site(1).kind(), "Fe");
+    }
+
+    #[test]
+    fn test_alloy_is_deterministic_given_a_seed() {
+        let alloy = || Alloy::try_new(vec!["Fe", "Ni"], vec![1, 1]).unwrap();
+        let lattice = Lattice::bcc(1.0).expand_all(20);
+        let kinds = |seed| {
+            let mut rng = StdRng::seed_from_u64(seed);
+            let lattice = lattice.clone().alloy_sites("A", alloy(), &mut rng);
+            lattice
+                .sites()
+                .iter()
+                .map(|site| site.kind().to_string())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(kinds(7), kinds(7));
+        assert_ne!(kinds(7), kinds(8));
+        assert!(kinds(7).iter().skip(1).step_by(2).all(|kind| kind == "B"));
+    }
+
+    #[test]
+    fn test_metadata_is_only_serialized_when_present() {
+        let lattice = Lattice::sc(1.0);
+        let data = serde_json::to_string(&lattice).unwrap();
+        assert!(!data.contains("metadata"));
+        let lattice = lattice.with_metadata("seeds", "alloy:42");
+        let data = serde_json::to_string(&lattice).unwrap();
+        assert!(data.contains(r#""metadata":{"seeds":"alloy:42"}"#));
+        let lattice: Lattice = data.parse().unwrap();
+        assert_eq!(lattice.metadata()["seeds"], "alloy:42");
+    }
+
+    #[test]
+    fn test_alloy_exact_follows_the_ratios() {
+        let alloy = Alloy::try_new(vec!["Fe", "Ni"], vec![1, 3]).unwrap();
+        let mut rng = StdRng::seed_from_u64(3);
+        let lattice = Lattice::bcc(1.0)
+            .expand_all(3)
+            .alloy_sites_exact("A", alloy, &mut rng);
+        let count = |kind| lattice.sites().iter().filter(|s| s.kind() == kind).count();
+        assert_eq!(count("Fe"), 7);
+        assert_eq!(count("Ni"), 20);
+        assert_eq!(count("B"), 27);
+        assert_eq!(count("A"), 0);
+    }
+
+    #[test]
+    fn test_alloy_graded_follows_the_gradient() {
+        let gradient = Gradient::Planar {
+            from: (0.0, 0.0, 0.0),
+            to: (0.0, 0.0, 9.0),
+        };
+        let alloy =
+            GradedAlloy::try_new(vec!["Fe", "Ni"], vec![1.0, 0.0], vec![0.0, 1.0], gradient)
+                .unwrap();
+        let mut rng = StdRng::seed_from_u64(5);
+        let lattice = Lattice::sc(1.0)
+            .expand(10, 10, 10)
+            .alloy_sites_graded("A", alloy, &mut rng);
+        let count = |z: f64, kind| {
+            lattice
+                .sites()
+                .iter()
+                .filter(|s| s.position().2 == z && s.kind() == kind)
+                .count()
+        };
+        assert_eq!(count(0.0, "Fe"), 100);
+        assert_eq!(count(9.0, "Ni"), 100);
+        assert!(count(2.0, "Fe") > count(2.0, "Ni"));
+        assert!(count(7.0, "Fe") < count(7.0, "Ni"));
+    }
+
+    #[test]
+    fn test_alloy_where_selects_a_sublattice_after_expansion() {
+        let alloy = Alloy::try_new(vec!["Fe"], vec![1]).unwrap();
+        let selector = Selector::Sublattice(1);
+        let lattice =
+            Lattice::bcc(1.0)
+                .expand(3, 2, 4)
+                .alloy_sites_where(&selector, alloy, &mut rand::rng());
+        assert!(lattice.sites().iter().step_by(2).all(|s| s.kind() == "A"));
+        assert!(
+            lattice
+                .sites()
+                .iter()
+                .skip(1)
+                .step_by(2)
+                .all(|s| s.kind() == "Fe")
+        );
+    }
+
+    #[test]
+    fn test_expansion_records_the_basis_of_the_sites() {
+        let lattice = Lattice::bcc(1.0).expand_x(2).expand_y(2);
+        let bases: Vec<_> = lattice.sites().iter().map(|s| s.basis()).collect();
+        assert_eq!(
+            bases,
+            [0, 1].repeat(4).into_iter().map(Some).collect::<Vec<_>>()
+        );
+        let output: Lattice = serde_json::to_string(&lattice).unwrap().parse().unwrap();
+        assert_eq!(output.site(3).basis(), Some(1));
+        assert_eq!(Lattice::bcc(1.0).site(1).basis(), None);
+    }
+
+    #[test]
+    fn test_alloy_where_selects_a_sublattice_after_a_mask() {
+        // Pixels are half a unit wide, so the third one drops the A sites at
+        // odd x and the B sites no longer sit at odd indices
+        let image = image::RgbaImage::from_fn(4, 1, |i, _| {
+            image::Rgba([0, 0, 0, if i == 2 { 0 } else { 255 }])
+        });
+        let mask = Mask::new(image.into(), 2.0);
+        let alloy = Alloy::try_new(vec!["Fe"], vec![1]).unwrap();
+        let lattice = Lattice::bcc(1.0)
+            .expand_x(4)
+            .apply_mask_z(mask, &mut rand::rng())
+            .alloy_sites_where(&Selector::Sublattice(1), alloy, &mut rand::rng());
+        let kinds: Vec<_> = lattice.sites().iter().map(|s| s.kind()).collect();
+        assert_eq!(kinds, vec!["A", "Fe", "Fe", "A", "Fe", "Fe"]);
+    }
+
+    #[test]
+    fn test_alloy_exact_where_only_touches_tagged_sites() {
+        let sites = (0..10)
+            .map(|i| match i < 4 {
+                true => Site::new("A").with_tags(vec!["interface"]),
+                false => Site::new("A"),
+            })
+            .collect();
+        let lattice = Lattice::sc(1.0).try_with_sites(sites).unwrap();
+        let alloy = Alloy::try_new(vec!["Fe", "Ni"], vec![1, 1]).unwrap();
+        let selector = Selector::Tag("interface".to_string());
+        let lattice = lattice.alloy_sites_exact_where(&selector, alloy, &mut rand::rng());
+        let count = |kind| lattice.sites().iter().filter(|s| s.kind() == kind).count();
+        assert_eq!(count("Fe"), 2);
+        assert_eq!(count("Ni"), 2);
+        assert!(lattice.sites().iter().skip(4).all(|s| s.kind() == "A"));
+    }
+
+    #[test]
+    fn test_alloy_sqs_beats_random_draws() {
+        let lattice = Lattice::sc(1.0)
+            .expand_all(4)
+            .try_with_neighbor_shells(3)
+            .unwrap();
+        let alloy = || Alloy::try_new(vec!["Fe", "Ni"], vec![1, 3]).unwrap();
+        let disorder = |lattice: &Lattice| -> f64 {
+            ["nn1", "nn2", "nn3"]
+                .iter()
+                .flat_map(|tag| lattice.warren_cowley_tagged(tag))
+                .map(|(_, _, alpha)| alpha * alpha)
+                .sum()
+        };
+        let mut rng = StdRng::seed_from_u64(2);
+        let sqs = lattice
+            .clone()
+            .alloy_sites_sqs("A", alloy(), 20000, &mut rng);
+        let random = lattice.alloy_sites_exact("A", alloy(), &mut rng);
+        assert!(disorder(&sqs) < disorder(&random) / 10.0);
+        let count = |kind| sqs.sites().iter().filter(|s| s.kind() == kind).count();
+        assert_eq!(count("Fe"), 16);
+        assert_eq!(count("Ni"), 48);
+    }
+
+    #[test]
+    fn test_alloy_spec_tags_follow_the_kinds() {
+        let spec = AlloySpec::try_new(
+            vec!["A", "B"],
+            vec![
+                TargetSpec::new("Fe", 1.0),
+                TargetSpec::new("Ni", 1.0).with_tags(vec!["dopant"]),
+            ],
+        )
+        .unwrap();
+        let alloy = Alloy::try_from_spec(&spec).unwrap();
+        let mut rng = StdRng::seed_from_u64(9);
+        let lattice = Lattice::bcc(1.0)
+            .expand_all(3)
+            .try_with_neighbor_shells(2)
+            .unwrap()
+            .alloy_sites_sqs_where(&spec.selector(), alloy, 1000, &mut rng);
+        assert!(
+            lattice
+                .sites()
+                .iter()
+                .all(|s| s.has_tag("dopant") == (s.kind() == "Ni"))
+        );
+        let count = |kind| lattice.sites().iter().filter(|s| s.kind() == kind).count();
+        assert_eq!(count("Fe"), 27);
+        assert_eq!(count("Ni"), 27);
+    }
+
+    #[test]
+    fn test_alloy_ordered_reaches_the_targets() {
+        let lattice = Lattice::sc(1.0).expand_all(6);
+        let alloy = || Alloy::try_new(vec!["Fe", "Ni"], vec![1, 1]).unwrap();
+        let mut rng = StdRng::seed_from_u64(11);
+        let ordered = lattice
+            .clone()
+            .try_alloy_sites_ordered("A", alloy(), &[("Fe", "Ni", -0.5)], 20000, &mut rng)
+            .unwrap();
+        let alpha = ordered
+            .warren_cowley()
+            .into_iter()
+            .find(|(i, j, _)| i == "Fe" && j == "Ni")
+            .unwrap()
+            .2;
+        assert!((alpha + 0.5).abs() < 1e-3, "alpha = {}", alpha);
+        let count = |kind| ordered.sites().iter().filter(|s| s.kind() == kind).count();
+        assert_eq!(count("Fe"), 108);
+        assert_eq!(count("Ni"), 108);
+    }
+
+    #[test]
+    fn test_alloy_ordered_measures_only_the_alloyed_sites() {
+        // The lower half is alloyed, the A sites above would dilute Fe and Ni
+        let selector = Selector::Within {
+            min: (0.0, 0.0, 0.0),
+            max: (5.0, 5.0, 2.0),
+        };
+        let alloy = Alloy::try_new(vec!["Fe", "Ni"], vec![1, 1]).unwrap();
+        let ordered = Lattice::sc(1.0)
+            .expand_all(6)
+            .try_alloy_sites_ordered_where(
+                &selector,
+                alloy,
+                &[("Fe", "Ni", -0.5)],
+                20000,
+                &mut StdRng::seed_from_u64(11),
+            )
+            .unwrap();
+        let alloyed: Vec<_> = (0..ordered.sites().len())
+            .filter(|&i| ordered.site(i).kind() != "A")
+            .collect();
+        assert_eq!(alloyed.len(), 108);
+        let pairs = ordered.pairs_among(&alloyed, 1, |_| Some(0));
+        let (fe, ni) = (pairs.kind("Fe").unwrap(), pairs.kind("Ni").unwrap());
+        let alpha = pairs.alpha(0, fe, ni).unwrap();
+        assert!((alpha + 0.5).abs() < 1e-3, "alpha = {}", alpha);
+    }
+
+    #[test]
+    fn test_alloy_ordered_fails_on_unknown_kinds() {
+        let alloy = || Alloy::try_new(vec!["Fe", "Ni"], vec![1, 1]).unwrap();
+        let lattice = Lattice::sc(1.0).expand_all(2);
+        let mut rng = rand::rng();
+        for order in [("Fe", "Nii", -0.2), ("A", "Fe", -0.2)] {
+            let result =
+                lattice
+                    .clone()
+                    .try_alloy_sites_ordered("A", alloy(), &[order], 10, &mut rng);
+            assert!(matches!(result, Err(VegasLatticeError::InvalidSpec(_))));
+        }
+    }
+
+    #[test]
+    fn test_warren_cowley_tagged() {
+        let lattice = Lattice::bcc(1.0)
+            .expand_all(2)
+            .try_with_neighbor_shells(2)
+            .unwrap();
+        let nn1 = lattice.warren_cowley_tagged("nn1");
+        let nn2 = lattice.warren_cowley_tagged("nn2");
+        assert!(nn1.contains(&("A".to_string(), "B".to_string(), -1.0)));
+        assert!(nn2.contains(&("A".to_string(), "B".to_string(), 1.0)));
+    }
+
     #[test]
     fn test_with_edges() {
         let lattice = Lattice::sc(1.0)
@@ -466,6 +1405,42 @@ mod test {
         assert!(result.is_err());
     }
 
+    #[test]
+    fn test_flat_and_mirrored_lattices_are_accepted() {
+        assert!(Lattice::try_new((1.0, 0.0, 1.0)).is_ok());
+        assert!(Lattice::sc(1.0).try_with_size((0.0, 0.0, 0.0)).is_ok());
+        let mirrored = [(0.0, 1.0, 0.0), (1.0, 0.0, 0.0), (0.0, 0.0, 1.0)];
+        let lattice = Lattice::sc(1.0).try_with_vectors(mirrored).unwrap();
+        assert_eq!(
+            lattice
+                .try_with_neighbors_within(1.1)
+                .unwrap()
+                .edges()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_fractional_coordinates_fail_without_volume() {
+        let lattice = Lattice::sc(1.0).try_with_size((1.0, 1.0, 0.0)).unwrap();
+        let result = lattice.to_fractional((0.5, 0.5, 0.0));
+        assert!(matches!(result, Err(VegasLatticeError::ZeroVolume)));
+    }
+
+    #[test]
+    fn test_bond_fails_without_volume() {
+        let data = r#"{
+            "size": [1.0, 1.0, 0.0],
+            "sites": [{"kind": "Fe", "position": [0.0, 0.0, 0.0]}],
+            "edges": []
+        }"#;
+        let result = data
+            .parse::<Lattice>()
+            .and_then(|lattice| lattice.try_with_neighbors_within(1.1));
+        assert!(matches!(result, Err(VegasLatticeError::ZeroVolume)));
+    }
+
     #[test]
     fn test_lattice_can_be_read_from_string() {
         let lattice = r#"{
@@ -489,4 +1464,84 @@ mod test {
         assert_eq!(lattice.sites().len(), 1);
         assert_eq!(lattice.edges().len(), 1);
     }
+
+    #[test]
+    fn test_lattice_without_vectors_is_orthogonal() {
+        let lattice: Lattice = r#"{"size": [1.0, 2.0, 3.0], "sites": [], "edges": []}"#
+            .parse()
+            .unwrap();
+        assert_eq!(
+            lattice.vectors(),
+            [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)]
+        );
+        assert_eq!(
+            lattice.cell(),
+            [(1.0, 0.0, 0.0), (0.0, 2.0, 0.0), (0.0, 0.0, 3.0)]
+        );
+    }
+
+    #[test]
+    fn test_orthogonal_lattice_does_not_serialize_vectors() {
+        let data = serde_json::to_string(&Lattice::sc(1.0)).unwrap();
+        assert!(!data.contains("vectors"));
+    }
+
+    #[test]
+    fn test_lattice_vectors_survive_a_round_trip() {
+        let vectors = [(1.0, 0.0, 0.0), (0.5, 1.0, 0.0), (0.0, 0.0, 1.0)];
+        let lattice = Lattice::sc(1.0).try_with_vectors(vectors).unwrap();
+        let data = serde_json::to_string(&lattice).unwrap();
+        let lattice: Lattice = data.parse().unwrap();
+        assert_eq!(lattice.vectors(), vectors);
+    }
+
+    #[test]
+    fn test_with_cell() {
+        let lattice = Lattice::sc(1.0)
+            .try_with_cell([(2.0, 0.0, 0.0), (0.0, 3.0, 4.0), (0.0, 0.0, 1.0)])
+            .unwrap();
+        assert_eq!(lattice.size(), (2.0, 5.0, 1.0));
+        assert_eq!(lattice.vectors()[1], (0.0, 0.6, 0.8));
+        assert_eq!(lattice.cell()[1], (0.0, 3.0, 4.0));
+    }
+
+    #[test]
+    fn test_lattice_with_degenerate_vectors() {
+        let result =
+            Lattice::sc(1.0).try_with_vectors([(1.0, 0.0, 0.0), (2.0, 0.0, 0.0), (0.0, 0.0, 1.0)]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_fractional_coordinates() {
+        let lattice = Lattice::hcp(2.0, 3.0);
+        let (u, v, w) = lattice.to_fractional(lattice.site(1).position()).unwrap();
+        assert!((u - 1.0 / 3.0).abs() < 1e-10);
+        assert!((v - 2.0 / 3.0).abs() < 1e-10);
+        assert!((w - 0.5).abs() < 1e-10);
+    }
+
+    #[test]
+    fn test_cartesian_coordinates() {
+        let lattice = Lattice::hcp(2.0, 3.0);
+        let (x, y, z) = lattice.to_cartesian((1.0 / 3.0, 2.0 / 3.0, 0.5));
+        let (u, v, w) = lattice.site(1).position();
+        assert!((x - u).abs() < 1e-10);
+        assert!((y - v).abs() < 1e-10);
+        assert!((z - w).abs() < 1e-10);
+    }
+
+    #[test]
+    fn non_orthogonal_lattice_expansion() {
+        let lattice = Lattice::sc(2.0)
+            .try_with_vectors([(1.0, 0.0, 0.0), (0.5, 1.0, 0.0), (0.0, 0.5, 1.0)])
+            .unwrap();
+        let output = lattice.clone().expand(1, 2, 2);
+        assert_eq!(output.sites.len(), 4);
+        assert_eq!(output.site(1).position(), (1.0, 2.0, 0.0));
+        assert_eq!(output.site(2).position(), (0.0, 1.0, 2.0));
+        assert_eq!(output.site(3).position(), (1.0, 3.0, 2.0));
+        assert_eq!(output.size(), (2.0, 4.0, 4.0));
+        assert_eq!(output.vectors(), lattice.vectors());
+    }
 }
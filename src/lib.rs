@@ -8,7 +8,8 @@
 //! ## Lattice creation
 //!
 //! `vegas_lattice` provides simple ways to create cubic and body centered cubic
-//! lattices. But you can create any number of custom lattices by creating the
+//! lattices, as well as face centered cubic and hexagonal close packed ones.
+//! But you can create any number of custom lattices by creating the
 //! sites and edges manually.
 //!
 //! Here is an example of how to create a simple cubic lattice:
@@ -35,6 +36,20 @@
 //! assert_eq!(lattice.edges().len(), 8);
 //! ```
 //!
+//! And here is how to create a hexagonal close packed lattice, notice that
+//! the lattice vectors are no longer aligned with the cartesian axes:
+//!
+//! ```rust
+//! use vegas_lattice::Lattice;
+//!
+//! let lattice = Lattice::hcp(1.0, 1.633);
+//!
+//! assert_eq!(lattice.size(), (1.0, 1.0, 1.633));
+//! assert_eq!(lattice.vectors()[1], (-0.5, 0.5 * 3.0_f64.sqrt(), 0.0));
+//! assert_eq!(lattice.sites().len(), 2);
+//! assert_eq!(lattice.edges().len(), 12);
+//! ```
+//!
 //! ## Lattice expansion
 //!
 //!
@@ -58,6 +73,20 @@
 //! direction. The same operation can be applied to the _y_ and _z_. The edges
 //! of the lattice are also replicated.
 //!
+//! Huge samples do not need to be held in memory to be written, a
+//! `Supercell` computes the sites and edges of the expanded lattice as they
+//! are written:
+//!
+//! ```rust
+//! use vegas_lattice::Lattice;
+//!
+//! let lattice = Lattice::sc(1.0);
+//! let supercell = lattice.supercell(100, 100, 100);
+//!
+//! assert_eq!(supercell.site_count(), 1_000_000);
+//! assert_eq!(supercell.edges().nth(3).unwrap().target(), 2);
+//! ```
+//!
 //! ## Edge dropping
 //!
 //! Well, as you saw in the expand operation, we keep the edges that have a
@@ -113,7 +142,7 @@
 //! let mut rng = rand::rng();
 //! let lattice = Lattice::sc(1.0).alloy_sites("A", alloy, &mut rng);
 //! assert_eq!(lattice.sites().len(), 1);
-//! assert!(lattice.sites()[0].kind() == "B" || lattice.sites()[0].kind() == "C");
+//! assert!(lattice.site(0).kind() == "B" || lattice.site(0).kind() == "C");
 //! ```
 
 pub mod error;
@@ -121,14 +150,23 @@ pub mod io;
 
 mod alloy;
 mod edge;
+mod graded;
 mod lattice;
 mod mask;
+mod neighbor;
+mod parallel;
+mod selector;
 mod site;
+mod sro;
+mod supercell;
 mod util;
 
-pub use alloy::Alloy;
-pub use edge::Edge;
+pub use alloy::{Alloy, AlloySpec, TargetSpec};
+pub use edge::{Edge, Exchange};
+pub use graded::{GradedAlloy, Gradient, Profile};
 pub use lattice::Lattice;
 pub use mask::Mask;
-pub use site::Site;
+pub use selector::Selector;
+pub use site::{Site, SiteRef, Sites, SitesIter};
+pub use supercell::Supercell;
 pub use util::Tagged;
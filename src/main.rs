@@ -1,33 +1,72 @@
-use clap::{ArgAction, Parser, Subcommand, ValueEnum};
+use clap::{ArgAction, Args, Parser, Subcommand, ValueEnum};
+use rand::{RngExt, SeedableRng, rngs::StdRng};
 use std::{
-    collections::{HashMap, hash_map::Entry},
     error::Error,
     fs::File,
-    io::{Read, stdin},
+    io::{BufReader, BufWriter, Read, Write, stdin, stdout},
     path::{Path, PathBuf},
 };
-use vegas_lattice::{Alloy, Lattice, Mask, error::Result, io};
+use vegas_lattice::{
+    Alloy, AlloySpec, GradedAlloy, Gradient, Lattice, Mask, Profile, Selector, TargetSpec,
+    error::{Result, VegasLatticeError},
+    io,
+};
 
-fn read(input: Option<&Path>) -> Result<Lattice> {
-    let mut data = String::new();
-    if let Some(path) = input {
-        let mut file = File::open(path)?;
-        file.read_to_string(&mut data)?;
-    } else {
-        stdin().read_to_string(&mut data)?;
+fn read(input: Option<&Path>, from: InputFormat) -> Result<Lattice> {
+    let mut reader: Box<dyn Read> = match input {
+        Some(path) => Box::new(File::open(path)?),
+        None => Box::new(stdin().lock()),
     };
-    let lattice: Lattice = data.parse()?;
-    Ok(lattice)
+    // Peek at the first bytes to tell binary lattices apart
+    let mut header = Vec::with_capacity(io::MAGIC.len());
+    (&mut reader)
+        .take(io::MAGIC.len() as u64)
+        .read_to_end(&mut header)?;
+    let reader = BufReader::new(header.as_slice().chain(reader));
+    if io::is_binary(&header) {
+        return io::from_reader_binary(reader);
+    }
+    match from {
+        InputFormat::Json => io::from_reader_lattice(reader),
+        InputFormat::Vampire => io::from_reader_vampire(reader),
+        InputFormat::Poscar => io::from_reader_poscar(reader),
+        InputFormat::Cif => io::from_reader_cif(reader),
+        InputFormat::Xyz => io::from_reader_xyz(reader),
+    }
 }
 
-fn write(lattice: Lattice) {
-    println!("{}", serde_json::to_string(&lattice).unwrap());
+fn write(lattice: Lattice, binary: bool) -> Result<()> {
+    let mut writer = BufWriter::new(stdout().lock());
+    if binary {
+        io::to_writer_binary(&mut writer, &lattice)?;
+    } else {
+        serde_json::to_writer(&mut writer, &lattice)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
+    Ok(())
 }
 
 fn write_pretty(lattice: Lattice) {
     println!("{}", io::to_string_lattice(&lattice).unwrap());
 }
 
+/// Builds the random number generator for a command, drawing a seed if none
+/// is given, and records the seed in the metadata of the lattice so the
+/// command can be repeated. Seeds of successive commands are appended.
+fn seeded(lattice: Lattice, command: &str, seed: Option<u64>) -> (Lattice, StdRng) {
+    let seed = seed.unwrap_or_else(|| rand::rng().random());
+    let entry = format!("{}:{}", command, seed);
+    let seeds = match lattice.metadata().get("seeds") {
+        Some(seeds) => format!("{} {}", seeds, entry),
+        None => entry,
+    };
+    (
+        lattice.with_metadata("seeds", &seeds),
+        StdRng::seed_from_u64(seed),
+    )
+}
+
 fn check_error(res: Result<()>) {
     if let Err(e) = res {
         eprintln!("Error: {}", e);
@@ -39,20 +78,25 @@ fn check_error(res: Result<()>) {
 
 // Commands over here
 
-fn check(input: Option<&Path>) -> Result<()> {
-    let lattice = read(input)?;
-    write(lattice);
-    Ok(())
+fn check(input: Option<&Path>, global: &Global) -> Result<()> {
+    let lattice = read(input, global.from)?;
+    write(lattice, global.binary)
 }
 
-fn pretty(input: Option<&Path>) -> Result<()> {
-    let lattice = read(input)?;
+fn pretty(input: Option<&Path>, global: &Global) -> Result<()> {
+    let lattice = read(input, global.from)?;
     write_pretty(lattice);
     Ok(())
 }
 
-fn drop(input: Option<&Path>, drop_x: bool, drop_y: bool, drop_z: bool) -> Result<()> {
-    let mut lattice = read(input)?;
+fn drop(
+    input: Option<&Path>,
+    global: &Global,
+    drop_x: bool,
+    drop_y: bool,
+    drop_z: bool,
+) -> Result<()> {
+    let mut lattice = read(input, global.from)?;
     if drop_x {
         lattice = lattice.drop_x();
     }
@@ -62,127 +106,326 @@ fn drop(input: Option<&Path>, drop_x: bool, drop_y: bool, drop_z: bool) -> Resul
     if drop_z {
         lattice = lattice.drop_z();
     }
-    write(lattice);
-    Ok(())
+    write(lattice, global.binary)
 }
 
 fn expand(
     input: Option<&Path>,
-    along_x: Option<usize>,
-    along_y: Option<usize>,
-    along_z: Option<usize>,
+    global: &Global,
+    (along_x, along_y, along_z): (Option<usize>, Option<usize>, Option<usize>),
+    xyz: bool,
 ) -> Result<()> {
-    let mut lattice = read(input)?;
-    lattice = lattice.expand(
+    let lattice = read(input, global.from)?;
+    let supercell = lattice.supercell(
         along_x.unwrap_or(1),
         along_y.unwrap_or(1),
         along_z.unwrap_or(1),
     );
-    write(lattice);
+    // The expanded lattice is written as it is computed, never held in memory
+    let mut writer = BufWriter::new(stdout().lock());
+    if xyz {
+        io::to_writer_xyz_supercell(&mut writer, &supercell)?;
+    } else if global.binary {
+        io::to_writer_binary_supercell(&mut writer, &supercell)?;
+    } else {
+        serde_json::to_writer(&mut writer, &supercell)?;
+        writeln!(writer)?;
+    }
+    writer.flush()?;
     Ok(())
 }
 
-fn alloy(input: Option<&Path>, source: &str, targets: Vec<String>) -> Result<()> {
-    let kinds: Vec<_> = targets.iter().step_by(2).map(|s| s.as_str()).collect();
-    let ratios: Vec<_> = targets
+fn bond(
+    input: Option<&Path>,
+    global: &Global,
+    cutoff: Option<f64>,
+    shells: Option<usize>,
+    between: Vec<String>,
+) -> Result<()> {
+    let mut lattice = read(input, global.from)?;
+    let kinds: Vec<_> = between
+        .chunks(2)
+        .map(|pair| (pair[0].as_str(), pair[1].as_str()))
+        .collect();
+    lattice = match (cutoff, shells, kinds.is_empty()) {
+        (Some(cutoff), _, true) => lattice.try_with_neighbors_within(cutoff),
+        (Some(cutoff), _, false) => lattice.try_with_neighbors_within_kinds(cutoff, &kinds),
+        (None, Some(shells), true) => lattice.try_with_neighbor_shells(shells),
+        (None, Some(shells), false) => lattice.try_with_neighbor_shells_kinds(shells, &kinds),
+        (None, None, _) => Ok(lattice),
+    }?;
+    write(lattice, global.binary)
+}
+
+fn alloy(global: &Global, args: AlloyArgs) -> Result<()> {
+    let lattice = read(args.input.as_deref(), global.from)?;
+    if !args.end.is_empty() {
+        let selector = filter(Selector::Kind(args.source.clone()), &args.substitution);
+        let alloy = graded_alloy(&lattice, &args)?;
+        let (lattice, mut rng) = seeded(lattice, "alloy", global.seed);
+        let lattice = lattice.alloy_sites_graded_where(&selector, alloy, &mut rng);
+        return write(lattice, global.binary);
+    }
+    let targets = targets(&args.target)?
+        .into_iter()
+        .map(|(kind, ratio)| TargetSpec::new(kind, ratio))
+        .collect();
+    let spec = AlloySpec::try_new(vec![args.source.as_str()], targets)?;
+    substitute(lattice, global, &spec, &args.substitution)
+}
+
+fn alloy_spec(global: &Global, args: AlloySpecArgs) -> Result<()> {
+    let spec = read_spec(&args.spec)?;
+    let lattice = read(args.input.as_deref(), global.from)?;
+    substitute(lattice, global, &spec, &args.substitution)
+}
+
+/// Substitutes the sites chosen by the spec and the filters, the way the
+/// options tell
+fn substitute(
+    lattice: Lattice,
+    global: &Global,
+    spec: &AlloySpec,
+    args: &Substitution,
+) -> Result<()> {
+    let selector = filter(spec.selector(), args);
+    let alloy = Alloy::try_from_spec(spec)?;
+    let (mut lattice, mut rng) = seeded(lattice, "alloy", global.seed);
+    let order: Vec<_> = args
+        .order
         .iter()
-        .skip(1)
-        .step_by(2)
-        .map(|s| s.parse::<u32>().unwrap())
+        .map(|(i, j, alpha)| (i.as_str(), j.as_str(), *alpha))
         .collect();
-    let target: Vec<_> = kinds.into_iter().zip(ratios).collect();
-    let mut lattice = read(input)?;
-    let alloy = Alloy::try_from_targets(target)?;
-    let mut rng = rand::rng();
-    lattice = lattice.alloy_sites(source, alloy, &mut rng);
-    write(lattice);
-    Ok(())
+    lattice = if !order.is_empty() {
+        lattice.try_alloy_sites_ordered_where(&selector, alloy, &order, args.steps, &mut rng)?
+    } else if args.sqs {
+        lattice.alloy_sites_sqs_where(&selector, alloy, args.steps, &mut rng)
+    } else if args.exact {
+        lattice.alloy_sites_exact_where(&selector, alloy, &mut rng)
+    } else {
+        lattice.alloy_sites_where(&selector, alloy, &mut rng)
+    };
+    write(lattice, global.binary)
+}
+
+/// Reads an alloy specification, TOML if the extension says so and JSON
+/// otherwise
+fn read_spec(path: &Path) -> Result<AlloySpec> {
+    let data = std::fs::read_to_string(path)?;
+    match path.extension().and_then(|extension| extension.to_str()) {
+        Some("toml") => AlloySpec::from_str_toml(&data),
+        _ => AlloySpec::from_str_json(&data),
+    }
+}
+
+/// Parses the alternating kinds and ratios given to `--target` or `--end`
+fn targets(values: &[String]) -> Result<Vec<(&str, f64)>> {
+    values
+        .chunks(2)
+        .map(|pair| match pair[1].parse::<f64>() {
+            Ok(ratio) => Ok((pair[0].as_str(), ratio)),
+            Err(_) => Err(VegasLatticeError::InvalidSpec(format!(
+                "invalid ratio `{}` for {}",
+                pair[1], pair[0]
+            ))),
+        })
+        .collect()
+}
+
+/// Narrows the selector down to the sites that pass every filter
+fn filter(mut selector: Selector, args: &Substitution) -> Selector {
+    for tag in args.tag.iter() {
+        selector = selector.and(Selector::Tag(tag.clone()));
+    }
+    if let Some(&[x0, y0, z0, x1, y1, z1]) = args.within.as_deref() {
+        selector = selector.and(Selector::Within {
+            min: (x0, y0, z0),
+            max: (x1, y1, z1),
+        });
+    }
+    if let Some(basis) = args.sublattice {
+        selector = selector.and(Selector::Sublattice(basis));
+    }
+    selector
+}
+
+/// Builds the graded alloy going from the `--target` ratios to the `--end`
+/// ones, the gradient spans the sites of the lattice unless told otherwise
+fn graded_alloy(lattice: &Lattice, args: &AlloyArgs) -> Result<GradedAlloy> {
+    let start = targets(&args.target)?;
+    let end = targets(&args.end)?;
+    let mut kinds: Vec<&str> = Vec::new();
+    for &(kind, _) in start.iter().chain(end.iter()) {
+        if !kinds.contains(&kind) {
+            kinds.push(kind);
+        }
+    }
+    let ratios = |pairs: &[(&str, f64)]| -> Vec<f64> {
+        kinds
+            .iter()
+            .map(|kind| pairs.iter().find(|(k, _)| k == kind).map_or(0.0, |p| p.1))
+            .collect()
+    };
+    let positions = lattice.sites().iter().map(|site| site.position());
+    let gradient = match args.center.as_deref() {
+        Some(&[x, y, z]) => {
+            let center = (x, y, z);
+            let radius = args.radius.unwrap_or_else(|| {
+                positions
+                    .map(|(px, py, pz)| {
+                        ((px - x).powi(2) + (py - y).powi(2) + (pz - z).powi(2)).sqrt()
+                    })
+                    .fold(0.0, f64::max)
+            });
+            Gradient::Radial { center, radius }
+        }
+        _ => {
+            let coordinate = |(x, y, z): (f64, f64, f64)| match args.axis {
+                Axis::X => x,
+                Axis::Y => y,
+                Axis::Z => z,
+            };
+            let (from, to) = match args.range.as_deref() {
+                Some(&[from, to]) => (from, to),
+                _ => positions
+                    .map(coordinate)
+                    .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), value| {
+                        (low.min(value), high.max(value))
+                    }),
+            };
+            let point = |value| match args.axis {
+                Axis::X => (value, 0.0, 0.0),
+                Axis::Y => (0.0, value, 0.0),
+                Axis::Z => (0.0, 0.0, value),
+            };
+            Gradient::Planar {
+                from: point(from),
+                to: point(to),
+            }
+        }
+    };
+    GradedAlloy::try_new(kinds.clone(), ratios(&start), ratios(&end), gradient)?
+        .try_with_profile(args.profile.clone())
 }
 
-fn mask(input: Option<&Path>, path: &Path, plane: Plane, ppu: f64) -> Result<()> {
-    let mut lattice = read(input)?;
+fn mask(input: Option<&Path>, global: &Global, path: &Path, plane: Plane, ppu: f64) -> Result<()> {
+    let lattice = read(input, global.from)?;
     let mask = Mask::try_new(path, ppu)?;
-    let mut rng = rand::rng();
+    let (mut lattice, mut rng) = seeded(lattice, "mask", global.seed);
     lattice = match plane {
         Plane::XY => lattice.apply_mask_z(mask, &mut rng),
         Plane::XZ => lattice.apply_mask_y(mask, &mut rng),
         Plane::YZ => lattice.apply_mask_x(mask, &mut rng),
     };
-    write(lattice);
+    write(lattice, global.binary)
+}
+
+fn sro(input: Option<&Path>, global: &Global, tag: Option<String>) -> Result<()> {
+    let lattice = read(input, global.from)?;
+    let parameters = match tag {
+        Some(tag) => lattice.warren_cowley_tagged(&tag),
+        None => lattice.warren_cowley(),
+    };
+    for (i, j, alpha) in parameters {
+        println!("{}\t{}\t{}", i, j, alpha);
+    }
     Ok(())
 }
 
-fn into(input: Option<&Path>, format: Format) -> Result<()> {
-    let lattice = read(input)?;
+fn into(input: Option<&Path>, global: &Global, format: Format, bonds: bool) -> Result<()> {
+    let lattice = read(input, global.from)?;
+    let mut writer = BufWriter::new(stdout().lock());
     match format {
         Format::Tsv => {
             for site in lattice.sites().iter() {
                 let (x, y, z) = site.position();
-                println!("{}\t{}\t{}\t{}", x, y, z, site.kind())
-            }
-        }
-        Format::Xyz => {
-            for site in lattice.sites().iter() {
-                let (x, y, z) = site.position();
-                println!("{} {} {} {}", site.kind(), x, y, z)
-            }
-        }
-        Format::Vampire => {
-            println!("# Unit cell size");
-            let (x, y, z) = lattice.size();
-            println!("{} {} {}", x, y, z);
-            println!("# Unit cell lattice vectors");
-            println!("{} {} {}", 1.0, 0.0, 0.0);
-            println!("{} {} {}", 0.0, 1.0, 0.0);
-            println!("{} {} {}", 0.0, 0.0, 1.0);
-            println!("# Atoms");
-            let mut index = 0;
-            let materials =
-                lattice
-                    .sites()
-                    .iter()
-                    .map(|s| s.kind())
-                    .fold(HashMap::new(), |mut acc, item| {
-                        if let Entry::Vacant(entry) = acc.entry(item) {
-                            entry.insert(index);
-                            index += 1;
-                        }
-                        acc
-                    });
-            println!("{} {}", lattice.sites().len(), materials.len());
-            for (i, site) in lattice.sites().iter().enumerate() {
-                let (x, y, z) = site.position();
-                let material_id = materials.get(&site.kind()).unwrap();
-                println!("{} {} {} {} {}", i, x, y, z, material_id);
-            }
-            println!("# Interactions");
-            println!("{} isotropic", lattice.edges().len());
-            for (i, edge) in lattice.edges().iter().enumerate() {
-                let (dx, dy, dz) = edge.delta();
-                println!(
-                    "{} {} {} {} {} {} {}",
-                    i,
-                    edge.source(),
-                    edge.target(),
-                    dx,
-                    dy,
-                    dz,
-                    1.0
-                );
+                writeln!(writer, "{}\t{}\t{}\t{}", x, y, z, site.kind())?;
             }
         }
+        Format::Xyz => io::to_writer_xyz(&mut writer, &lattice)?,
+        Format::Vampire => io::to_writer_vampire(&mut writer, &lattice)?,
+        Format::Poscar => io::to_writer_poscar(&mut writer, &lattice)?,
+        Format::Lammps => io::to_writer_lammps(&mut writer, &lattice, bonds)?,
+        Format::Vtk => io::to_writer_vtk(&mut writer, &lattice)?,
+        Format::Graphml => io::to_writer_graphml(&mut writer, &lattice)?,
+        Format::Dot => io::to_writer_dot(&mut writer, &lattice)?,
     }
+    writer.flush()?;
     Ok(())
 }
 
+/// Parses a target order like `Fe:Ni=-0.2`
+fn parse_order(value: &str) -> std::result::Result<(String, String, f64), String> {
+    let (pair, alpha) = value
+        .split_once('=')
+        .ok_or("expected a target like Fe:Ni=-0.2")?;
+    let (i, j) = pair
+        .split_once(':')
+        .ok_or("expected a pair of kinds like Fe:Ni")?;
+    let alpha = alpha
+        .parse()
+        .map_err(|e| format!("invalid parameter: {}", e))?;
+    Ok((i.to_string(), j.to_string(), alpha))
+}
+
+/// Parses a profile, either `linear` or a list of points like `0:0,0.5:0.2,1:1`
+fn parse_profile(value: &str) -> std::result::Result<Profile, String> {
+    if value == "linear" {
+        return Ok(Profile::Linear);
+    }
+    let points = value
+        .split(',')
+        .map(|point| {
+            let (t, fraction) = point
+                .split_once(':')
+                .ok_or("expected `linear` or points like 0:0,0.5:0.2,1:1")?;
+            let t = t
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid point: {}", e))?;
+            let fraction = fraction
+                .trim()
+                .parse()
+                .map_err(|e| format!("invalid point: {}", e))?;
+            Ok((t, fraction))
+        })
+        .collect::<std::result::Result<Vec<_>, String>>()?;
+    Ok(Profile::Custom(points))
+}
+
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+enum InputFormat {
+    /// Vegas lattice JSON file format
+    #[default]
+    Json,
+    /// Vampire unit cell file format
+    Vampire,
+    /// VASP POSCAR file format
+    Poscar,
+    /// Crystallographic information file format
+    Cif,
+    /// Extended XYZ file format
+    Xyz,
+}
+
 #[derive(Debug, Clone, ValueEnum)]
 enum Format {
-    /// XYZ file format
+    /// Extended XYZ file format
     Xyz,
     /// TSV file format
     Tsv,
     /// Vampire unit cell file format
     Vampire,
+    /// VASP POSCAR file format
+    Poscar,
+    /// LAMMPS data file format
+    Lammps,
+    /// Legacy VTK file format
+    Vtk,
+    /// GraphML file format
+    Graphml,
+    /// Graphviz DOT file format
+    Dot,
 }
 
 #[derive(Debug, Default, Clone, ValueEnum)]
@@ -196,6 +439,17 @@ enum Plane {
     YZ,
 }
 
+#[derive(Debug, Default, Clone, Copy, ValueEnum)]
+enum Axis {
+    /// The x axis.
+    X,
+    /// The y axis.
+    Y,
+    /// The z axis.
+    #[default]
+    Z,
+}
+
 #[derive(Debug, Subcommand)]
 enum SubCommand {
     /// Create a simple cubic lattice
@@ -216,6 +470,15 @@ enum SubCommand {
         /// Lattice parameter
         a: f64,
     },
+    /// Create a hexagonal close packed lattice
+    Hcp {
+        #[arg(long = "lattice-parameter", short, default_value = "1.0")]
+        /// Lattice parameter
+        a: f64,
+        #[arg(long = "c-parameter", short)]
+        /// Lattice parameter along the c axis, defaults to the ideal sqrt(8/3) a
+        c: Option<f64>,
+    },
     /// Check lattice
     Check {
         /// Input file
@@ -253,22 +516,46 @@ enum SubCommand {
         #[arg(short, long = "along-z")]
         /// Expand lattice along z-axis
         z: Option<usize>,
+        /// Write the expanded lattice in the extended XYZ format instead
+        #[arg(long, default_value = "false", conflicts_with = "binary")]
+        xyz: bool,
     },
-    /// Create an alloy
-    Alloy {
-        /// Source kind
-        source: String,
+    /// Generate the edges between sites closer than a cutoff or within some shells
+    Bond {
+        /// Input file
+        input: Option<PathBuf>,
+        #[arg(
+            short,
+            long,
+            required_unless_present = "shells",
+            conflicts_with = "shells"
+        )]
+        /// Cutoff distance
+        cutoff: Option<f64>,
+        #[arg(short, long)]
+        /// Number of neighbor shells, edges get tagged as nn1, nn2, ...
+        shells: Option<usize>,
         #[arg(
             short,
             long,
-            value_names = ["target", "ratio"],
+            value_names = ["source", "target"],
             number_of_values = 2,
             action = ArgAction::Append,
         )]
-        /// Target kind with is corresponding ratio
-        target: Vec<String>,
+        /// Only join sites with the given pair of kinds
+        between: Vec<String>,
+    },
+    /// Create an alloy
+    Alloy(Box<AlloyArgs>),
+    /// Create an alloy described by a specification file
+    AlloySpec(Box<AlloySpecArgs>),
+    /// Report the Warren-Cowley short-range order parameters of the lattice
+    Sro {
         /// Input file
         input: Option<PathBuf>,
+        /// Only use the edges with this tag as neighbors, like nn1
+        #[arg(short, long)]
+        tag: Option<String>,
     },
     /// Apply a mask
     Mask {
@@ -289,14 +576,136 @@ enum SubCommand {
         format: Format,
         /// Input file
         input: Option<PathBuf>,
+        /// Write the edges as bonds, for the formats that support it
+        #[arg(short, long, default_value = "false")]
+        bonds: bool,
     },
 }
 
+/// Options of the alloy command
+#[derive(Debug, Args)]
+struct AlloyArgs {
+    /// Source kind
+    source: String,
+    #[arg(
+        short,
+        long,
+        value_names = ["target", "ratio"],
+        number_of_values = 2,
+        action = ArgAction::Append,
+    )]
+    /// Target kind with is corresponding ratio
+    target: Vec<String>,
+    /// Input file
+    input: Option<PathBuf>,
+    #[command(flatten)]
+    substitution: Substitution,
+    /// Target kind with its ratio at the end of a composition gradient, the
+    /// --target ratios are used at the start
+    #[arg(
+        long,
+        value_names = ["target", "ratio"],
+        number_of_values = 2,
+        action = ArgAction::Append,
+        conflicts_with_all = ["exact", "order", "sqs"],
+    )]
+    end: Vec<String>,
+    /// Axis of the composition gradient
+    #[arg(long, default_value = "z", requires = "end")]
+    axis: Axis,
+    /// Coordinates of the start and end of the gradient along the axis,
+    /// defaults to the extent of the sites
+    #[arg(long, value_names = ["from", "to"], number_of_values = 2, requires = "end")]
+    range: Option<Vec<f64>>,
+    /// Make the gradient radial around this point instead of along an axis
+    #[arg(
+        long,
+        value_names = ["x", "y", "z"],
+        number_of_values = 3,
+        requires = "end",
+        conflicts_with = "range",
+    )]
+    center: Option<Vec<f64>>,
+    /// Distance from the center where the end ratios are reached, defaults
+    /// to the farthest site
+    #[arg(long, requires = "center")]
+    radius: Option<f64>,
+    /// Profile of the gradient, `linear` or the fraction of the end ratios
+    /// at some points along it, like `0:0,0.5:0.2,1:1`
+    #[arg(long, default_value = "linear", value_parser = parse_profile, requires = "end")]
+    profile: Profile,
+}
+
+/// Options of the alloy-spec command
+#[derive(Debug, Args)]
+struct AlloySpecArgs {
+    /// Alloy specification file, JSON or TOML, with the source kinds and the
+    /// targets with their fractions and tags
+    spec: PathBuf,
+    /// Input file
+    input: Option<PathBuf>,
+    #[command(flatten)]
+    substitution: Substitution,
+}
+
+/// Options shared by the alloy commands, on how and where to substitute
+#[derive(Debug, Args)]
+struct Substitution {
+    /// Follow the ratios exactly instead of drawing each site independently
+    #[arg(short, long, default_value = "false")]
+    exact: bool,
+    /// Target Warren-Cowley parameter between two kinds, as `Fe:Ni=-0.2`,
+    /// sites are swapped to approach it, implies --exact
+    #[arg(short, long, value_parser = parse_order, action = ArgAction::Append)]
+    order: Vec<(String, String, f64)>,
+    /// Build a special quasirandom structure, swapping sites so the pair
+    /// correlations over the neighbor shells match a random alloy, implies
+    /// --exact
+    #[arg(long, default_value = "false", conflicts_with = "order")]
+    sqs: bool,
+    /// Maximum number of swaps tried to reach the target order or the
+    /// quasirandom structure
+    #[arg(long, default_value = "100000")]
+    steps: usize,
+    /// Only alloy the sites with this tag
+    #[arg(long, action = ArgAction::Append)]
+    tag: Vec<String>,
+    /// Only alloy the sites inside the box between two corners
+    #[arg(
+        long,
+        value_names = ["xmin", "ymin", "zmin", "xmax", "ymax", "zmax"],
+        number_of_values = 6,
+        allow_negative_numbers = true,
+    )]
+    within: Option<Vec<f64>>,
+    /// Only alloy the sites at this index of the unit cell the lattice was
+    /// expanded from, like `1` for the B sites of an expanded bcc lattice
+    #[arg(long, value_name = "index")]
+    sublattice: Option<usize>,
+}
+
 #[derive(Debug, Parser)]
 #[command(author, version, about, long_about)]
 struct Cli {
     #[clap(subcommand)]
     subcmd: SubCommand,
+    #[command(flatten)]
+    global: Global,
+}
+
+/// Options shared by all the commands
+#[derive(Debug, Args)]
+struct Global {
+    /// Format of the input lattice
+    #[arg(long, global = true, default_value = "json")]
+    from: InputFormat,
+    /// Write the output lattice in the compact binary format
+    #[arg(long, global = true, default_value = "false")]
+    binary: bool,
+    /// Seed for the random number generator, a random one is drawn and
+    /// recorded in the lattice metadata if missing
+    #[arg(long, global = true)]
+    seed: Option<u64>,
 }
 
 fn main() {
@@ -305,35 +714,50 @@ fn main() {
     let result = match cli.subcmd {
         SubCommand::Sc { a } => {
             let lattice = Lattice::sc(a);
-            write(lattice);
-            Ok(())
+            write(lattice, cli.global.binary)
         }
         SubCommand::Bcc { a } => {
             let lattice = Lattice::bcc(a);
-            write(lattice);
-            Ok(())
+            write(lattice, cli.global.binary)
         }
         SubCommand::Fcc { a } => {
             let lattice = Lattice::fcc(a);
-            write(lattice);
-            Ok(())
+            write(lattice, cli.global.binary)
         }
-        SubCommand::Check { input } => check(input.as_deref()),
-        SubCommand::Pretty { input } => pretty(input.as_deref()),
-        SubCommand::Drop { input, x, y, z } => drop(input.as_deref(), x, y, z),
-        SubCommand::Expand { input, x, y, z } => expand(input.as_deref(), x, y, z),
-        SubCommand::Alloy {
-            source,
-            target,
+        SubCommand::Hcp { a, c } => {
+            let lattice = Lattice::hcp(a, c.unwrap_or((8.0_f64 / 3.0).sqrt() * a));
+            write(lattice, cli.global.binary)
+        }
+        SubCommand::Check { input } => check(input.as_deref(), &cli.global),
+        SubCommand::Pretty { input } => pretty(input.as_deref(), &cli.global),
+        SubCommand::Drop { input, x, y, z } => drop(input.as_deref(), &cli.global, x, y, z),
+        SubCommand::Expand {
+            input,
+            x,
+            y,
+            z,
+            xyz,
+        } => expand(input.as_deref(), &cli.global, (x, y, z), xyz),
+        SubCommand::Bond {
             input,
-        } => alloy(input.as_deref(), &source, target),
+            cutoff,
+            shells,
+            between,
+        } => bond(input.as_deref(), &cli.global, cutoff, shells, between),
+        SubCommand::Alloy(args) => alloy(&cli.global, *args),
+        SubCommand::AlloySpec(args) => alloy_spec(&cli.global, *args),
+        SubCommand::Sro { input, tag } => sro(input.as_deref(), &cli.global, tag),
         SubCommand::Mask {
             mask: mask_path,
             input,
             plane,
             ppu,
-        } => mask(input.as_deref(), &mask_path, plane, ppu),
-        SubCommand::Into { format, input } => into(input.as_deref(), format),
+        } => mask(input.as_deref(), &cli.global, &mask_path, plane, ppu),
+        SubCommand::Into {
+            format,
+            input,
+            bonds,
+        } => into(input.as_deref(), &cli.global, format, bonds),
     };
 
     check_error(result);
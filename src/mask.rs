@@ -26,13 +26,18 @@ pub struct Mask {
 }
 
 impl Mask {
+    /// Create a new mask from an image and a pixels per unit ratio
+    pub fn new(image: DynamicImage, ppu: f64) -> Self {
+        Self {
+            image: Box::new(image),
+            ppu,
+        }
+    }
+
     /// Create a new mask from a path and a pixels per unit ratio
     pub fn try_new(path: &Path, ppu: f64) -> Result<Self, VegasLatticeError> {
         let img = image::open(path)?;
-        Ok(Self {
-            image: Box::new(img),
-            ppu,
-        })
+        Ok(Self::new(img, ppu))
     }
 
     /// Computes whether to keep a site or not given the coordinates of the site
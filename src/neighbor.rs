@@ -0,0 +1,242 @@
+//! Finds the pairs of sites of a lattice that lay within a given distance
+
+use crate::{
+    edge::Edge,
+    error::Result,
+    lattice::Lattice,
+    site::SiteRef,
+    util::{Vector, add, dot, norm, scale, sub},
+};
+
+/// Distances below this value are considered the same site
+const TOLERANCE: f64 = 1e-8;
+
+/// Relative difference below which two distances belong to the same shell
+const SHELL_TOLERANCE: f64 = 1e-6;
+
+/// Number of cells, along the longest cell vector, the search for neighbor
+/// shells may reach before giving up
+const MAX_SHELL_IMAGES: f64 = 8.0;
+
+/// A pair of sites together with the distance between them
+#[derive(Debug, Clone)]
+pub(crate) struct Neighbor {
+    pub(crate) source: usize,
+    pub(crate) target: usize,
+    pub(crate) delta: (i32, i32, i32),
+    pub(crate) distance: f64,
+}
+
+impl Neighbor {
+    pub(crate) fn edge(&self) -> Edge {
+        Edge::new(self.source, self.target, self.delta)
+    }
+}
+
+/// Number of periodic images to scan along each axis so that no pair closer
+/// than `cutoff` is missed
+fn image_range(lattice: &Lattice, cutoff: f64) -> Result<[i32; 3]> {
+    let reciprocal = lattice.reciprocal_cell()?;
+    let mut range = [0; 3];
+    for (axis, vector) in reciprocal.iter().enumerate() {
+        let fractional: Vec<_> = lattice
+            .sites()
+            .iter()
+            .map(|site| dot(site.position(), *vector))
+            .collect();
+        let min = fractional.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = fractional.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let span = if fractional.is_empty() {
+            0.0
+        } else {
+            max - min
+        };
+        range[axis] = (cutoff * norm(*vector) + span).ceil() as i32;
+    }
+    Ok(range)
+}
+
+/// Whether the image `delta` should be used to pair a site with itself, this
+/// keeps only one of `delta` and `-delta`.
+#[inline]
+fn is_positive(delta: (i32, i32, i32)) -> bool {
+    delta > (0, 0, 0)
+}
+
+/// Finds all the pairs of sites closer than `cutoff`, scanning the periodic
+/// images of the lattice, every pair is reported only once.
+///
+/// Only the pairs of sites for which `filter` returns `true` are reported.
+/// Fails when the cell has no volume.
+pub(crate) fn neighbors_within<F>(
+    lattice: &Lattice,
+    cutoff: f64,
+    filter: &F,
+) -> Result<Vec<Neighbor>>
+where
+    F: Fn(SiteRef, SiteRef) -> bool,
+{
+    let cell = lattice.cell();
+    let [nx, ny, nz] = image_range(lattice, cutoff)?;
+    let offsets: Vec<((i32, i32, i32), Vector)> = (-nx..=nx)
+        .flat_map(|i| (-ny..=ny).flat_map(move |j| (-nz..=nz).map(move |k| (i, j, k))))
+        .map(|(i, j, k)| {
+            let offset = add(
+                add(scale(cell[0], i as f64), scale(cell[1], j as f64)),
+                scale(cell[2], k as f64),
+            );
+            ((i, j, k), offset)
+        })
+        .collect();
+    let sites = lattice.sites();
+    let mut neighbors = Vec::new();
+    for (source, site) in sites.iter().enumerate() {
+        for (target, other) in sites.iter().enumerate().skip(source) {
+            if !filter(site, other) {
+                continue;
+            }
+            let difference = sub(other.position(), site.position());
+            for &(delta, offset) in offsets.iter() {
+                if source == target && !is_positive(delta) {
+                    continue;
+                }
+                let distance = norm(add(difference, offset));
+                if distance > TOLERANCE && distance < cutoff {
+                    neighbors.push(Neighbor {
+                        source,
+                        target,
+                        delta,
+                        distance,
+                    });
+                }
+            }
+        }
+    }
+    Ok(neighbors)
+}
+
+/// Finds all the pairs of sites within the first `shells` neighbor shells,
+/// together with the shell they belong to, starting at 1 for the nearest
+/// neighbors.
+///
+/// Only the pairs of sites for which `filter` returns `true` are reported.
+/// The search stops `MAX_SHELL_IMAGES` cells away, so fewer shells are
+/// returned when the pairs accepted by the filter are that far apart. Fails
+/// when the cell has no volume.
+pub(crate) fn neighbor_shells<F>(
+    lattice: &Lattice,
+    shells: usize,
+    filter: F,
+) -> Result<Vec<(Neighbor, usize)>>
+where
+    F: Fn(SiteRef, SiteRef) -> bool,
+{
+    // Every cell vector has some length once the cell has a volume
+    lattice.reciprocal_cell()?;
+    let sites = lattice.sites();
+    let any_pair = sites
+        .iter()
+        .enumerate()
+        .any(|(i, site)| sites.iter().skip(i).any(|other| filter(site, other)));
+    if shells == 0 || !any_pair {
+        return Ok(Vec::new());
+    }
+    let lengths = lattice.cell().map(norm);
+    let mut cutoff = lengths.iter().cloned().fold(f64::INFINITY, f64::min);
+    let limit = MAX_SHELL_IMAGES * lengths.iter().cloned().fold(0.0, f64::max);
+    // Grow the cutoff until enough shells show up, every pair closer than the
+    // cutoff is found, so the shells we get are complete.
+    loop {
+        let mut neighbors = neighbors_within(lattice, cutoff, &filter)?;
+        neighbors.sort_by(|a, b| a.distance.total_cmp(&b.distance));
+        let mut radius = f64::NEG_INFINITY;
+        let mut shell = 0;
+        let mut tagged = Vec::with_capacity(neighbors.len());
+        for neighbor in neighbors {
+            if neighbor.distance - radius > SHELL_TOLERANCE * neighbor.distance {
+                radius = neighbor.distance;
+                shell += 1;
+            }
+            if shell > shells {
+                break;
+            }
+            tagged.push((neighbor, shell));
+        }
+        if shell >= shells || cutoff > limit {
+            return Ok(tagged);
+        }
+        cutoff *= 1.5;
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{neighbor_shells, neighbors_within};
+    use crate::{Lattice, Site};
+
+    #[test]
+    fn simple_cubic_has_three_nearest_neighbors() {
+        let neighbors = neighbors_within(&Lattice::sc(1.0), 1.1, &|_, _| true).unwrap();
+        assert_eq!(neighbors.len(), 3);
+    }
+
+    #[test]
+    fn simple_cubic_has_nine_next_nearest_neighbors() {
+        let neighbors = neighbors_within(&Lattice::sc(1.0), 1.5, &|_, _| true).unwrap();
+        assert_eq!(neighbors.len(), 9);
+    }
+
+    #[test]
+    fn large_cutoffs_reach_far_images() {
+        let neighbors = neighbors_within(&Lattice::sc(1.0), 2.1, &|_, _| true).unwrap();
+        assert!(neighbors.iter().any(|n| n.delta == (2, 0, 0)));
+    }
+
+    #[test]
+    fn filter_restricts_the_pairs() {
+        let neighbors =
+            neighbors_within(&Lattice::bcc(1.0), 1.1, &|s, t| s.kind() != t.kind()).unwrap();
+        assert_eq!(neighbors.len(), 8);
+    }
+
+    #[test]
+    fn body_centered_cubic_shells() {
+        let neighbors = neighbor_shells(&Lattice::bcc(1.0), 2, |_, _| true).unwrap();
+        assert_eq!(neighbors.iter().filter(|(_, shell)| *shell == 1).count(), 8);
+        assert_eq!(neighbors.iter().filter(|(_, shell)| *shell == 2).count(), 6);
+    }
+
+    #[test]
+    fn absent_kinds_have_no_shells() {
+        let neighbors = neighbor_shells(&Lattice::bcc(1.0), 1, |s, t| {
+            s.kind() == "X" && t.kind() == "Y"
+        })
+        .unwrap();
+        assert!(neighbors.is_empty());
+    }
+
+    #[test]
+    fn shells_stop_at_the_search_limit() {
+        // There are far more shells than the search reaches, the ones found
+        // are returned
+        let lattice = Lattice::sc(1.0)
+            .try_with_sites(vec![Site::new("A"), Site::new("B")])
+            .unwrap();
+        let neighbors = neighbor_shells(&lattice, 100, |s, t| s.kind() != t.kind()).unwrap();
+        assert!(!neighbors.is_empty());
+    }
+
+    #[test]
+    fn flat_cells_can_not_be_searched() {
+        let lattice = Lattice::sc(1.0).try_with_size((1.0, 1.0, 0.0)).unwrap();
+        assert!(neighbors_within(&lattice, 1.1, &|_, _| true).is_err());
+        assert!(neighbor_shells(&lattice, 1, |_, _| true).is_err());
+    }
+
+    #[test]
+    fn far_shells_are_complete() {
+        // Third shell of the simple cubic lattice are the body diagonals
+        let neighbors = neighbor_shells(&Lattice::sc(1.0), 3, |_, _| true).unwrap();
+        assert_eq!(neighbors.len(), 13);
+    }
+}
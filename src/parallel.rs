@@ -0,0 +1,152 @@
+//! Helpers to run the per site and per edge operations in parallel when the
+//! `parallel` feature is enabled, and sequentially otherwise.
+//!
+//! Randomized operations draw a seed from the caller's generator and use a
+//! generator seeded from it for every chunk of items. Chunks have a fixed
+//! size, so the results only depend on the seed, not on the number of
+//! threads or on whether the feature is enabled.
+
+use rand::{SeedableRng, rngs::StdRng};
+#[cfg(feature = "parallel")]
+use rayon::prelude::*;
+
+/// Number of items that share a random number generator
+const CHUNK_SIZE: usize = 4096;
+
+/// Computes `f(i)` for every `i` in `0..count`, keeping the order
+#[cfg(feature = "parallel")]
+pub(crate) fn map_range<U, F>(count: usize, f: F) -> Vec<U>
+where
+    U: Send,
+    F: Fn(usize) -> U + Sync + Send,
+{
+    (0..count).into_par_iter().map(f).collect()
+}
+
+/// Computes `f(i)` for every `i` in `0..count`, keeping the order
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn map_range<U, F>(count: usize, f: F) -> Vec<U>
+where
+    U: Send,
+    F: Fn(usize) -> U + Sync + Send,
+{
+    (0..count).map(f).collect()
+}
+
+/// Computes `f(i, item)` for every item and its index, keeping the items
+/// for which it returns something, in order
+#[cfg(feature = "parallel")]
+pub(crate) fn filter_map_indexed<T, U, F>(items: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(usize, T) -> Option<U> + Sync + Send,
+{
+    items
+        .into_par_iter()
+        .enumerate()
+        .filter_map(|(i, item)| f(i, item))
+        .collect()
+}
+
+/// Computes `f(i, item)` for every item and its index, keeping the items
+/// for which it returns something, in order
+#[cfg(not(feature = "parallel"))]
+pub(crate) fn filter_map_indexed<T, U, F>(items: Vec<T>, f: F) -> Vec<U>
+where
+    T: Send,
+    U: Send,
+    F: Fn(usize, T) -> Option<U> + Sync + Send,
+{
+    items
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, item)| f(i, item))
+        .collect()
+}
+
+/// Number of set flags before every position, the chunks are counted in
+/// parallel and only their totals are added up in sequence
+pub(crate) fn count_before(flags: &[bool]) -> Vec<usize> {
+    let chunks: Vec<_> = flags.chunks(CHUNK_SIZE).collect();
+    let totals = map_range(chunks.len(), |chunk| {
+        chunks[chunk].iter().filter(|&&flag| flag).count()
+    });
+    let starts: Vec<_> = totals
+        .iter()
+        .scan(0, |sum, total| {
+            let start = *sum;
+            *sum += total;
+            Some(start)
+        })
+        .collect();
+    let counts = map_range(chunks.len(), |chunk| {
+        chunks[chunk]
+            .iter()
+            .scan(starts[chunk], |sum, &flag| {
+                let count = *sum;
+                *sum += usize::from(flag);
+                Some(count)
+            })
+            .collect::<Vec<_>>()
+    });
+    counts.into_iter().flatten().collect()
+}
+
+/// Computes `f(item, rng)` for every item, keeping the order, where `rng` is
+/// seeded from `seed` and the chunk the item belongs to
+pub(crate) fn map_with_rng<T, U, F>(items: &[T], seed: u64, f: F) -> Vec<U>
+where
+    T: Sync,
+    U: Send,
+    F: Fn(&T, &mut StdRng) -> U + Sync + Send,
+{
+    let chunks = map_range(items.len().div_ceil(CHUNK_SIZE), |chunk| {
+        let mut rng = StdRng::seed_from_u64(seed.wrapping_add(chunk as u64));
+        let end = ((chunk + 1) * CHUNK_SIZE).min(items.len());
+        items[chunk * CHUNK_SIZE..end]
+            .iter()
+            .map(|item| f(item, &mut rng))
+            .collect::<Vec<_>>()
+    });
+    chunks.into_iter().flatten().collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CHUNK_SIZE, count_before, filter_map_indexed, map_range, map_with_rng};
+    use rand::RngExt;
+
+    #[test]
+    fn map_range_keeps_the_order() {
+        assert_eq!(map_range(5, |i| 2 * i), vec![0, 2, 4, 6, 8]);
+    }
+
+    #[test]
+    fn filter_map_indexed_keeps_the_order() {
+        let items = vec!["a", "b", "c", "d"];
+        let kept = filter_map_indexed(items, |i, item| (i % 2 == 1).then_some(item));
+        assert_eq!(kept, vec!["b", "d"]);
+    }
+
+    #[test]
+    fn count_before_spans_chunks() {
+        let flags: Vec<_> = (0..2 * CHUNK_SIZE + 3).map(|i| i % 3 == 0).collect();
+        let counts = count_before(&flags);
+        assert_eq!(counts.len(), flags.len());
+        assert_eq!(&counts[..5], &[0, 1, 1, 1, 2]);
+        let last = flags.len() - 1;
+        assert_eq!(counts[last], flags[..last].iter().filter(|&&f| f).count());
+    }
+
+    #[test]
+    fn map_with_rng_depends_only_on_the_seed() {
+        let items: Vec<_> = (0..3 * CHUNK_SIZE + 5).collect();
+        let first = map_with_rng(&items, 42, |_, rng| rng.random::<u32>());
+        let second = map_with_rng(&items, 42, |_, rng| rng.random::<u32>());
+        let third = map_with_rng(&items, 43, |_, rng| rng.random::<u32>());
+        assert_eq!(first.len(), items.len());
+        assert_eq!(first, second);
+        assert_ne!(first, third);
+    }
+}
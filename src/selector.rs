@@ -0,0 +1,144 @@
+//! Let's choose which sites of a lattice an operation applies to
+
+use crate::{SiteRef, Tagged};
+
+/// Chooses sites of a lattice by kind, tag, position or place in the unit
+/// cell, used to alloy only part of a sample.
+///
+/// # Example
+///
+/// ```rust
+/// use vegas_lattice::{Alloy, Lattice, Selector};
+///
+/// // Alloy only the A sites in the lower half of the sample
+/// let selector = Selector::Kind("A".to_string()).and(Selector::Within {
+///     min: (0.0, 0.0, 0.0),
+///     max: (4.0, 4.0, 1.5),
+/// });
+/// let alloy = Alloy::try_new(vec!["Fe"], vec![1]).unwrap();
+/// let lattice = Lattice::bcc(1.0)
+///     .expand_all(4)
+///     .alloy_sites_where(&selector, alloy, &mut rand::rng());
+///
+/// let count = |kind| lattice.sites().iter().filter(|s| s.kind() == kind).count();
+/// assert_eq!(count("Fe"), 32);
+/// assert_eq!(count("A"), 32);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selector {
+    /// Every site
+    All,
+    /// The sites of the given kind
+    Kind(String),
+    /// The sites with the given tag
+    Tag(String),
+    /// The sites inside the box between the given corners, boundaries included
+    Within {
+        min: (f64, f64, f64),
+        max: (f64, f64, f64),
+    },
+    /// The sites at the given index of the unit cell, as recorded when the
+    /// lattice is first expanded, so it survives masks. Sites that were never
+    /// expanded have no such index and are never chosen.
+    Sublattice(usize),
+    /// The sites chosen by every one of the selectors
+    And(Vec<Selector>),
+    /// The sites chosen by any of the selectors
+    Or(Vec<Selector>),
+}
+
+impl Selector {
+    /// Chooses the sites chosen by both selectors
+    pub fn and(self, other: Selector) -> Selector {
+        match self {
+            Selector::And(mut selectors) => {
+                selectors.push(other);
+                Selector::And(selectors)
+            }
+            selector => Selector::And(vec![selector, other]),
+        }
+    }
+
+    /// Returns `true` if the site is chosen
+    pub fn matches<'a>(&self, site: impl Into<SiteRef<'a>>) -> bool {
+        self.matches_ref(site.into())
+    }
+
+    fn matches_ref(&self, site: SiteRef) -> bool {
+        match self {
+            Selector::All => true,
+            Selector::Kind(kind) => site.kind() == kind,
+            Selector::Tag(tag) => site.has_tag(tag),
+            Selector::Within { min, max } => {
+                let (x, y, z) = site.position();
+                (min.0..=max.0).contains(&x)
+                    && (min.1..=max.1).contains(&y)
+                    && (min.2..=max.2).contains(&z)
+            }
+            Selector::Sublattice(basis) => site.basis() == Some(*basis),
+            Selector::And(selectors) => selectors.iter().all(|s| s.matches_ref(site)),
+            Selector::Or(selectors) => selectors.iter().any(|s| s.matches_ref(site)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Selector;
+    use crate::Site;
+
+    #[test]
+    fn selects_by_kind_and_tag() {
+        let site = Site::new("Fe").with_tags(vec!["core"]);
+        assert!(Selector::Kind("Fe".to_string()).matches(&site));
+        assert!(!Selector::Kind("Ni".to_string()).matches(&site));
+        assert!(Selector::Tag("core".to_string()).matches(&site));
+        assert!(!Selector::Tag("shell".to_string()).matches(&site));
+        assert!(!Selector::Tag("core".to_string()).matches(&Site::new("Fe")));
+    }
+
+    #[test]
+    fn selects_within_a_box() {
+        let selector = Selector::Within {
+            min: (0.0, 0.0, 0.0),
+            max: (1.0, 1.0, 1.0),
+        };
+        assert!(selector.matches(&Site::new("Fe").with_position((1.0, 0.5, 0.0))));
+        assert!(!selector.matches(&Site::new("Fe").with_position((1.5, 0.5, 0.0))));
+    }
+
+    #[test]
+    fn selects_by_sublattice() {
+        let selector = Selector::Sublattice(1);
+        let site = Site::new("Fe");
+        assert!(!selector.matches(&site));
+        assert!(selector.matches(&site.clone().with_basis(1)));
+        assert!(!selector.matches(&site.with_basis(0)));
+    }
+
+    #[test]
+    fn and_requires_every_selector() {
+        let selector = Selector::Kind("Fe".to_string())
+            .and(Selector::Tag("core".to_string()))
+            .and(Selector::Sublattice(0));
+        assert_eq!(selector.clone(), selector);
+        let site = Site::new("Fe").with_tags(vec!["core"]).with_basis(0);
+        assert!(selector.matches(&site));
+        assert!(!selector.matches(&site.clone().with_basis(1)));
+        assert!(!selector.matches(&Site::new("Fe")));
+        assert!(Selector::And(vec![]).matches(&Site::new("Ni")));
+        assert!(Selector::All.matches(&Site::new("Ni")));
+    }
+
+    #[test]
+    fn or_requires_any_selector() {
+        let selector = Selector::Or(vec![
+            Selector::Kind("Fe".to_string()),
+            Selector::Kind("Ni".to_string()),
+        ]);
+        assert!(selector.matches(&Site::new("Fe")));
+        assert!(selector.matches(&Site::new("Ni")));
+        assert!(!selector.matches(&Site::new("Co")));
+        assert!(!Selector::Or(vec![]).matches(&Site::new("Fe")));
+    }
+}
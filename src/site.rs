@@ -1,7 +1,10 @@
-use crate::util::Tagged;
-use serde::{Deserialize, Serialize};
+use crate::util::{Tagged, Vector, add, scale};
+use serde::{
+    Deserialize, Deserializer, Serialize, Serializer,
+    de::{SeqAccess, Visitor},
+};
 use serde_json::Error as SerdeError;
-use std::str::FromStr;
+use std::{fmt, slice, str::FromStr, sync::Arc};
 
 /// Represetns a site in a lattice.
 ///
@@ -9,6 +12,12 @@ use std::str::FromStr;
 /// The `position` field is a tuple of the x, y, and z coordinates of the site within the
 /// lattice.
 ///
+/// The `basis` field is the index of the site in the unit cell it was
+/// expanded from, it is set the first time a lattice is expanded.
+///
+/// Lattices do not store their sites like this, they keep a table of their
+/// kinds and every site refers to its kind by index, see `SiteRef`.
+///
 /// # Exalples
 ///
 /// Here is an example of how to create a site and access its fields:
@@ -26,6 +35,8 @@ pub struct Site {
     kind: String,
     position: (f64, f64, f64),
     tags: Option<Vec<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    basis: Option<usize>,
 }
 
 impl FromStr for Site {
@@ -50,6 +61,7 @@ impl Site {
             kind: kind.to_string(),
             position: (0.0, 0.0, 0.0),
             tags: None,
+            basis: None,
         }
     }
 
@@ -63,22 +75,30 @@ impl Site {
         &self.kind
     }
 
-    /// Move along the x axis
-    pub fn move_x(mut self, distance: f64) -> Self {
-        self.position.0 += distance;
+    /// Return the index of the site in its unit cell, if known
+    pub fn basis(&self) -> Option<usize> {
+        self.basis
+    }
+
+    /// Move along the given direction, usually one of the lattice vectors
+    pub fn move_along(mut self, vector: (f64, f64, f64), distance: f64) -> Self {
+        self.position = add(self.position, scale(vector, distance));
         self
     }
 
+    /// Move along the x axis
+    pub fn move_x(self, distance: f64) -> Self {
+        self.move_along((1.0, 0.0, 0.0), distance)
+    }
+
     /// Move along the y axis
-    pub fn move_y(mut self, distance: f64) -> Self {
-        self.position.1 += distance;
-        self
+    pub fn move_y(self, distance: f64) -> Self {
+        self.move_along((0.0, 1.0, 0.0), distance)
     }
 
     /// Move along the z axis
-    pub fn move_z(mut self, distance: f64) -> Self {
-        self.position.2 += distance;
-        self
+    pub fn move_z(self, distance: f64) -> Self {
+        self.move_along((0.0, 0.0, 1.0), distance)
     }
 
     /// Changes the kind of the site
@@ -98,12 +118,312 @@ impl Site {
         self.tags = Some(tags.iter().map(|s| s.to_string()).collect());
         self
     }
+
+    /// Changes the index of the site in its unit cell
+    pub fn with_basis(mut self, basis: usize) -> Self {
+        self.basis = Some(basis);
+        self
+    }
+}
+
+/// A site of a lattice, borrowed from it. The kind is resolved through the
+/// kind table of the lattice.
+///
+/// # Examples
+///
+/// ```rust
+/// use vegas_lattice::Lattice;
+///
+/// let lattice = Lattice::bcc(2.0);
+/// let site = lattice.site(1);
+///
+/// assert_eq!(site.kind(), "B");
+/// assert_eq!(site.position(), (1.0, 1.0, 1.0));
+/// assert_eq!(site.to_site().kind(), "B");
+/// ```
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+#[serde(rename = "Site")]
+pub struct SiteRef<'a> {
+    kind: &'a str,
+    position: (f64, f64, f64),
+    tags: Option<&'a [String]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    basis: Option<usize>,
+}
+
+impl Tagged for SiteRef<'_> {
+    fn tags(&self) -> Option<Vec<&str>> {
+        SiteRef::tags(self)
+    }
+}
+
+impl<'a> From<&'a Site> for SiteRef<'a> {
+    fn from(site: &'a Site) -> Self {
+        SiteRef {
+            kind: &site.kind,
+            position: site.position,
+            tags: site.tags.as_deref(),
+            basis: site.basis,
+        }
+    }
 }
 
+impl<'a> SiteRef<'a> {
+    /// Return the position of the site
+    pub fn position(&self) -> (f64, f64, f64) {
+        self.position
+    }
+
+    /// Return the kind of the site
+    pub fn kind(&self) -> &'a str {
+        self.kind
+    }
+
+    /// Return the tags of the site, borrowed from the lattice
+    pub fn tags(&self) -> Option<Vec<&'a str>> {
+        self.tags
+            .map(|tags| tags.iter().map(|tag| tag.as_ref()).collect())
+    }
+
+    /// Return the index of the site in its unit cell, if known
+    pub fn basis(&self) -> Option<usize> {
+        self.basis
+    }
+
+    /// Copies the site out of the lattice
+    pub fn to_site(&self) -> Site {
+        Site {
+            kind: self.kind.to_string(),
+            position: self.position,
+            tags: self.tags.map(|tags| tags.to_vec()),
+            basis: self.basis,
+        }
+    }
+
+    /// Moves the site along the given direction
+    pub(crate) fn move_along(mut self, vector: Vector, distance: f64) -> Self {
+        self.position = add(self.position, scale(vector, distance));
+        self
+    }
+
+    /// Sets the index in the unit cell, unless the site already has one
+    pub(crate) fn or_basis(mut self, basis: usize) -> Self {
+        self.basis.get_or_insert(basis);
+        self
+    }
+}
+
+/// A site as stored by a lattice, the kind is an index into the kind table.
+/// Tags are shared, so the copies made when expanding do not allocate, but
+/// adding tags to a site gives it a list of its own.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Record {
+    pub(crate) kind: u32,
+    pub(crate) position: Vector,
+    pub(crate) tags: Option<Arc<[String]>>,
+    pub(crate) basis: Option<usize>,
+}
+
+impl Record {
+    /// Copy of the record moved along the given direction, the record keeps
+    /// its index in the unit cell or takes the given one
+    pub(crate) fn moved(&self, vector: Vector, distance: f64, basis: usize) -> Self {
+        Record {
+            kind: self.kind,
+            position: add(self.position, scale(vector, distance)),
+            tags: self.tags.clone(),
+            basis: self.basis.or(Some(basis)),
+        }
+    }
+
+    /// Adds the tags the site does not have yet
+    pub(crate) fn add_tags(&mut self, tags: &[String]) {
+        let current = self.tags.as_deref().unwrap_or_default();
+        if tags.iter().all(|tag| current.contains(tag)) {
+            return;
+        }
+        let mut merged = current.to_vec();
+        for tag in tags.iter() {
+            if !merged.contains(tag) {
+                merged.push(tag.clone());
+            }
+        }
+        self.tags = Some(merged.into());
+    }
+}
+
+/// The sites of a lattice along with the table of their kinds, every kind is
+/// stored once and sites refer to it by its index. Lattices usually have a
+/// handful of kinds, so a linear search beats hashing.
+///
+/// It serializes as a sequence of sites, each with its kind spelled out.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct SiteTable {
+    pub(crate) kinds: Vec<String>,
+    pub(crate) records: Vec<Record>,
+}
+
+impl SiteTable {
+    /// Index of the given kind, adding it to the table if needed
+    pub(crate) fn intern(&mut self, kind: &str) -> u32 {
+        match self.kinds.iter().position(|known| known == kind) {
+            Some(index) => index as u32,
+            None => {
+                self.kinds.push(kind.to_string());
+                (self.kinds.len() - 1) as u32
+            }
+        }
+    }
+
+    /// Appends a site
+    pub(crate) fn push(&mut self, site: Site) {
+        let kind = self.intern(&site.kind);
+        self.records.push(Record {
+            kind,
+            position: site.position,
+            tags: site.tags.map(Arc::from),
+            basis: site.basis,
+        });
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// The site at the given index, resolving its kind
+    pub(crate) fn get(&self, index: usize) -> SiteRef<'_> {
+        self.resolve(&self.records[index])
+    }
+
+    pub(crate) fn resolve<'a>(&'a self, record: &'a Record) -> SiteRef<'a> {
+        SiteRef {
+            kind: &self.kinds[record.kind as usize],
+            position: record.position,
+            tags: record.tags.as_deref(),
+            basis: record.basis,
+        }
+    }
+
+    pub(crate) fn view(&self) -> Sites<'_> {
+        Sites { table: self }
+    }
+}
+
+impl FromIterator<Site> for SiteTable {
+    fn from_iter<I: IntoIterator<Item = Site>>(sites: I) -> Self {
+        let mut table = SiteTable::default();
+        for site in sites {
+            table.push(site);
+        }
+        table
+    }
+}
+
+impl Serialize for SiteTable {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.view())
+    }
+}
+
+impl<'de> Deserialize<'de> for SiteTable {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct TableVisitor;
+
+        impl<'de> Visitor<'de> for TableVisitor {
+            type Value = SiteTable;
+
+            fn expecting(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+                formatter.write_str("a sequence of sites")
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<SiteTable, A::Error> {
+                // Sites are interned one by one, so their kinds are not kept
+                let mut table = SiteTable::default();
+                while let Some(site) = seq.next_element::<Site>()? {
+                    table.push(site);
+                }
+                Ok(table)
+            }
+        }
+
+        deserializer.deserialize_seq(TableVisitor)
+    }
+}
+
+/// The sites of a lattice, see `Lattice::sites`
+#[derive(Clone, Copy, Debug)]
+pub struct Sites<'a> {
+    table: &'a SiteTable,
+}
+
+impl<'a> Sites<'a> {
+    /// Number of sites
+    pub fn len(&self) -> usize {
+        self.table.len()
+    }
+
+    /// Returns `true` if there are no sites
+    pub fn is_empty(&self) -> bool {
+        self.table.records.is_empty()
+    }
+
+    /// The site at the given index, if any
+    pub fn get(&self, index: usize) -> Option<SiteRef<'a>> {
+        let table = self.table;
+        table.records.get(index).map(|record| table.resolve(record))
+    }
+
+    /// Iterates over the sites
+    pub fn iter(&self) -> SitesIter<'a> {
+        SitesIter {
+            table: self.table,
+            records: self.table.records.iter(),
+        }
+    }
+}
+
+impl<'a> IntoIterator for Sites<'a> {
+    type Item = SiteRef<'a>;
+    type IntoIter = SitesIter<'a>;
+
+    fn into_iter(self) -> SitesIter<'a> {
+        self.iter()
+    }
+}
+
+/// Iterator over the sites of a lattice
+#[derive(Clone, Debug)]
+pub struct SitesIter<'a> {
+    table: &'a SiteTable,
+    records: slice::Iter<'a, Record>,
+}
+
+impl<'a> Iterator for SitesIter<'a> {
+    type Item = SiteRef<'a>;
+
+    fn next(&mut self) -> Option<SiteRef<'a>> {
+        let table = self.table;
+        self.records.next().map(|record| table.resolve(record))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.records.size_hint()
+    }
+}
+
+impl DoubleEndedIterator for SitesIter<'_> {
+    fn next_back(&mut self) -> Option<Self::Item> {
+        let table = self.table;
+        self.records.next_back().map(|record| table.resolve(record))
+    }
+}
+
+impl ExactSizeIterator for SitesIter<'_> {}
+
 #[cfg(test)]
 mod test {
-    use super::Site;
-    use std::str::FromStr;
+    use super::{Record, Site, SiteTable};
+    use std::{str::FromStr, sync::Arc};
 
     #[test]
     fn site_can_be_created() {
@@ -118,6 +438,12 @@ mod test {
         assert_eq!(site.position, (1.0, 0.0, 0.0));
     }
 
+    #[test]
+    fn site_can_be_moved_along_a_vector() {
+        let site = Site::new("Fe").move_along((0.5, 1.0, 0.0), 2.0);
+        assert_eq!(site.position, (1.0, 2.0, 0.0));
+    }
+
     #[test]
     fn site_can_be_changed() {
         let site = Site::new("Fe").with_kind("Cu");
@@ -139,6 +465,49 @@ mod test {
         );
     }
 
+    #[test]
+    fn record_tags_can_be_added() {
+        let mut record = Record {
+            kind: 0,
+            position: (0.0, 0.0, 0.0),
+            tags: Some(Arc::from(vec!["core".to_string()])),
+            basis: None,
+        };
+        let shared = record.clone();
+        record.add_tags(&["core".to_string()]);
+        assert!(Arc::ptr_eq(
+            record.tags.as_ref().unwrap(),
+            shared.tags.as_ref().unwrap()
+        ));
+        record.add_tags(&["core".to_string(), "dopant".to_string()]);
+        assert_eq!(
+            record.tags.as_deref(),
+            Some(&["core".to_string(), "dopant".to_string()][..])
+        );
+    }
+
+    #[test]
+    fn kinds_are_stored_once() {
+        let table: SiteTable = vec![Site::new("Fe"), Site::new("Fe"), Site::new("Ni")]
+            .into_iter()
+            .collect();
+        assert_eq!(table.kinds, vec!["Fe".to_string(), "Ni".to_string()]);
+        let kinds: Vec<_> = table.records.iter().map(|record| record.kind).collect();
+        assert_eq!(kinds, vec![0, 0, 1]);
+        assert_eq!(table.get(2).kind(), "Ni");
+    }
+
+    #[test]
+    fn site_table_serializes_like_sites() {
+        let sites = vec![Site::new("Fe").with_tags(vec!["core"]), Site::new("Ni")];
+        let table: SiteTable = sites.clone().into_iter().collect();
+        let data = serde_json::to_string(&table).unwrap();
+        assert_eq!(data, serde_json::to_string(&sites).unwrap());
+        let output: SiteTable = serde_json::from_str(&data).unwrap();
+        assert_eq!(output.kinds, table.kinds);
+        assert_eq!(output.records, table.records);
+    }
+
     #[test]
     fn site_can_be_read_from_string() {
         let data = r#"
@@ -0,0 +1,358 @@
+//! Chemical short-range order, as measured by the Warren–Cowley parameters
+//!
+//! For kinds _i_ and _j_ the parameter is `α_ij = 1 - p_ij / c_j`, where
+//! `p_ij` is the probability that a neighbor of a site of kind _i_ is of kind
+//! _j_ and `c_j` is the concentration of _j_. It is zero for a random
+//! solution, negative when _i_ and _j_ attract each other and positive when
+//! they repel each other. Neighbors are the ends of the edges, so edges
+//! crossing the periodic boundaries count as well.
+
+use crate::{
+    Edge, Lattice, Tagged,
+    error::{Result, VegasLatticeError},
+};
+use rand::{Rng, RngExt};
+
+/// Squared error below which the targets are considered met
+const TOLERANCE: f64 = 1e-8;
+
+/// Counts of the pairs of neighbor kinds of a lattice, the edges are split in
+/// groups, for instance neighbor shells, each with its own counts. Counts are
+/// updated incrementally as sites swap kinds.
+pub(crate) struct PairCounts {
+    kinds: Vec<String>,
+    sites: Vec<usize>,
+    edges: Vec<(usize, usize, usize)>,
+    incident: Vec<Vec<usize>>,
+    groups: usize,
+    counts: Vec<f64>,
+    concentrations: Vec<f64>,
+}
+
+impl PairCounts {
+    /// Counts the pairs of the lattice, `group` tells the group of each edge
+    /// or `None` to leave it out
+    pub(crate) fn new<F>(lattice: &Lattice, groups: usize, group: F) -> Self
+    where
+        F: Fn(&Edge) -> Option<usize>,
+    {
+        // Kinds are numbered as in the kind table of the lattice
+        let table = lattice.site_table();
+        let kinds = table.kinds.clone();
+        let sites: Vec<_> = table
+            .records
+            .iter()
+            .map(|record| record.kind as usize)
+            .collect();
+        let edges: Vec<_> = lattice
+            .edges()
+            .iter()
+            .filter_map(|edge| group(edge).map(|g| (edge.source(), edge.target(), g)))
+            .collect();
+        let mut incident = vec![Vec::new(); sites.len()];
+        for (index, &(source, target, _)) in edges.iter().enumerate() {
+            incident[source].push(index);
+            if target != source {
+                incident[target].push(index);
+            }
+        }
+        let mut concentrations = vec![0.0; kinds.len()];
+        for &kind in sites.iter() {
+            concentrations[kind] += 1.0 / sites.len() as f64;
+        }
+        let mut pairs = PairCounts {
+            counts: vec![0.0; groups * kinds.len() * kinds.len()],
+            kinds,
+            sites,
+            edges,
+            incident,
+            groups,
+            concentrations,
+        };
+        for index in 0..pairs.edges.len() {
+            pairs.count(index, 1.0);
+        }
+        pairs
+    }
+
+    fn count(&mut self, edge: usize, weight: f64) {
+        let n = self.kinds.len();
+        let (source, target, group) = self.edges[edge];
+        let (i, j) = (self.sites[source], self.sites[target]);
+        self.counts[(group * n + i) * n + j] += weight;
+        self.counts[(group * n + j) * n + i] += weight;
+    }
+
+    /// Index of the given kind, if present in the lattice
+    pub(crate) fn kind(&self, kind: &str) -> Option<usize> {
+        self.kinds.iter().position(|k| k == kind)
+    }
+
+    /// Takes the concentrations over the given sites instead of the whole
+    /// lattice, for the parameters of a part of it
+    pub(crate) fn within(mut self, sites: &[usize]) -> Self {
+        self.concentrations = vec![0.0; self.kinds.len()];
+        for (kind, concentration) in self.composition(sites) {
+            self.concentrations[kind] = concentration;
+        }
+        self
+    }
+
+    /// Resolves the kinds of targets given as `(i, j, alpha)` triplets, kinds
+    /// without sites are an error since their parameters are not defined
+    pub(crate) fn targets(
+        &self,
+        targets: &[(&str, &str, f64)],
+    ) -> Result<Vec<(usize, usize, f64)>> {
+        let kind = |kind: &str| match self.kind(kind) {
+            Some(index) if self.concentrations[index] > 0.0 => Ok(index),
+            _ => Err(VegasLatticeError::InvalidSpec(format!(
+                "no sites of kind {} for the order targets",
+                kind
+            ))),
+        };
+        targets
+            .iter()
+            .map(|&(i, j, alpha)| Ok((kind(i)?, kind(j)?, alpha)))
+            .collect()
+    }
+
+    /// The kind of every site, as an index into the kind table
+    pub(crate) fn sites(&self) -> &[usize] {
+        &self.sites
+    }
+
+    /// Warren–Cowley parameter of the pair of kinds within a group, `None`
+    /// if there are no neighbors of a site of kind _i_
+    pub(crate) fn alpha(&self, group: usize, i: usize, j: usize) -> Option<f64> {
+        debug_assert!(group < self.groups);
+        let n = self.kinds.len();
+        let row = &self.counts[(group * n + i) * n..(group * n + i + 1) * n];
+        let total: f64 = row.iter().sum();
+        if total == 0.0 || self.concentrations[j] == 0.0 {
+            return None;
+        }
+        Some(1.0 - row[j] / total / self.concentrations[j])
+    }
+
+    /// Swaps the kinds of two sites, updating the counts
+    pub(crate) fn swap(&mut self, a: usize, b: usize) {
+        let mut edges: Vec<_> = self.incident[a]
+            .iter()
+            .chain(self.incident[b].iter())
+            .copied()
+            .collect();
+        edges.sort_unstable();
+        edges.dedup();
+        for &edge in edges.iter() {
+            self.count(edge, -1.0);
+        }
+        self.sites.swap(a, b);
+        for &edge in edges.iter() {
+            self.count(edge, 1.0);
+        }
+    }
+
+    /// Swaps randomly chosen pairs of `candidates` with different kinds,
+    /// keeping the swaps that do not increase `error`, until the error
+    /// vanishes or `steps` swaps were tried.
+    pub(crate) fn minimize<R, F>(
+        &mut self,
+        candidates: &[usize],
+        steps: usize,
+        rng: &mut R,
+        error: F,
+    ) where
+        R: Rng,
+        F: Fn(&PairCounts) -> f64,
+    {
+        if candidates.len() < 2 {
+            return;
+        }
+        let mut current = error(self);
+        for _ in 0..steps {
+            if current < TOLERANCE {
+                break;
+            }
+            let a = candidates[rng.random_range(0..candidates.len())];
+            let b = candidates[rng.random_range(0..candidates.len())];
+            if self.sites[a] == self.sites[b] {
+                continue;
+            }
+            self.swap(a, b);
+            let trial = error(self);
+            if trial <= current {
+                current = trial;
+            } else {
+                self.swap(a, b);
+            }
+        }
+    }
+
+    /// Squared error of the Warren–Cowley parameters of the first group with
+    /// respect to the targets resolved by `targets`, the pairs whose first
+    /// kind has no neighbors are left out
+    pub(crate) fn error(&self, targets: &[(usize, usize, f64)]) -> f64 {
+        targets
+            .iter()
+            .filter_map(|&(i, j, target)| {
+                let alpha = self.alpha(0, i, j)?;
+                Some((alpha - target).powi(2))
+            })
+            .sum()
+    }
+
+    /// Concentration of each kind among the given sites, kinds missing from
+    /// them are left out
+    pub(crate) fn composition(&self, sites: &[usize]) -> Vec<(usize, f64)> {
+        let mut counts = vec![0usize; self.kinds.len()];
+        for &site in sites.iter() {
+            counts[self.sites[site]] += 1;
+        }
+        (0..counts.len())
+            .filter(|&kind| counts[kind] > 0)
+            .map(|kind| (kind, counts[kind] as f64 / sites.len() as f64))
+            .collect()
+    }
+
+    /// Squared distance to a random alloy of the given composition, that is
+    /// the sum of the squared Warren–Cowley parameters of its kinds taken
+    /// with respect to that composition. Group _k_ is weighted by `1 / (k + 1)`
+    /// so the closer shells matter more.
+    pub(crate) fn disorder(&self, composition: &[(usize, f64)]) -> f64 {
+        let n = self.kinds.len();
+        let mut error = 0.0;
+        for group in 0..self.groups {
+            let weight = 1.0 / (group + 1) as f64;
+            for &(i, _) in composition.iter() {
+                let row = &self.counts[(group * n + i) * n..(group * n + i + 1) * n];
+                let total: f64 = row.iter().sum();
+                if total == 0.0 {
+                    continue;
+                }
+                for &(j, concentration) in composition.iter() {
+                    error += weight * (1.0 - row[j] / total / concentration).powi(2);
+                }
+            }
+        }
+        error
+    }
+
+    /// Lists the Warren–Cowley parameters of every pair of kinds of a group
+    pub(crate) fn parameters(&self, group: usize) -> Vec<(String, String, f64)> {
+        let n = self.kinds.len();
+        (0..n)
+            .flat_map(|i| (0..n).map(move |j| (i, j)))
+            .filter_map(|(i, j)| {
+                let alpha = self.alpha(group, i, j)?;
+                Some((self.kinds[i].to_string(), self.kinds[j].to_string(), alpha))
+            })
+            .collect()
+    }
+}
+
+/// Neighbor shell of an edge as tagged by `try_with_neighbor_shells`, starting
+/// from zero for `nn1`
+pub(crate) fn shell(edge: &Edge) -> Option<usize> {
+    edge.tags()?
+        .iter()
+        .filter_map(|tag| tag.strip_prefix("nn")?.parse::<usize>().ok())
+        .find(|&shell| shell > 0)
+        .map(|shell| shell - 1)
+}
+
+/// Warren–Cowley parameters of every pair of kinds, using the edges accepted
+/// by `filter` as neighbors
+pub(crate) fn warren_cowley<F>(lattice: &Lattice, filter: F) -> Vec<(String, String, f64)>
+where
+    F: Fn(&Edge) -> bool,
+{
+    PairCounts::new(lattice, 1, |edge| filter(edge).then_some(0)).parameters(0)
+}
+
+/// Warren–Cowley parameters using only the edges with the given tag
+pub(crate) fn warren_cowley_tagged(lattice: &Lattice, tag: &str) -> Vec<(String, String, f64)> {
+    warren_cowley(lattice, |edge| edge.has_tag(tag))
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PairCounts, shell, warren_cowley};
+    use crate::{Edge, Lattice, Site};
+
+    fn alpha(parameters: &[(String, String, f64)], a: &str, b: &str) -> f64 {
+        parameters
+            .iter()
+            .find(|(i, j, _)| i == a && j == b)
+            .map(|(_, _, alpha)| *alpha)
+            .unwrap()
+    }
+
+    #[test]
+    fn ordered_bcc_is_fully_ordered() {
+        // Every neighbor of an A site is a B site
+        let parameters = warren_cowley(&Lattice::bcc(1.0).expand_all(2), |_| true);
+        assert_eq!(alpha(&parameters, "A", "B"), -1.0);
+        assert_eq!(alpha(&parameters, "A", "A"), 1.0);
+        assert_eq!(alpha(&parameters, "B", "A"), -1.0);
+    }
+
+    #[test]
+    fn swaps_keep_the_counts_consistent() {
+        let lattice = Lattice::bcc(1.0)
+            .expand_all(2)
+            .try_with_neighbor_shells(2)
+            .unwrap()
+            .try_with_sites(
+                (0..16)
+                    .map(|i| Site::new(if i % 3 == 0 { "Fe" } else { "Ni" }))
+                    .collect(),
+            )
+            .unwrap();
+        let mut pairs = PairCounts::new(&lattice, 1, |_| Some(0));
+        // Site 0 keeps its kind, so kinds are numbered the same way
+        pairs.swap(1, 3);
+        pairs.swap(6, 5);
+        let sites: Vec<_> = pairs
+            .sites()
+            .iter()
+            .map(|&kind| Site::new(&pairs.kinds[kind]))
+            .collect();
+        let fresh = PairCounts::new(&lattice.try_with_sites(sites).unwrap(), 1, |_| Some(0));
+        assert_eq!(pairs.counts, fresh.counts);
+    }
+
+    #[test]
+    fn shells_are_read_from_the_tags() {
+        let edge = Edge::new(0, 0, (1, 0, 0));
+        assert_eq!(shell(&edge), None);
+        assert_eq!(shell(&edge.clone().with_tags(vec!["nn1"])), Some(0));
+        assert_eq!(shell(&edge.clone().with_tags(vec!["bulk", "nn3"])), Some(2));
+        assert_eq!(shell(&edge.with_tags(vec!["nn0", "nnx"])), None);
+    }
+
+    #[test]
+    fn ordered_bcc_is_far_from_disorder() {
+        let lattice = Lattice::bcc(1.0).expand_all(2);
+        let pairs = PairCounts::new(&lattice, 1, |_| Some(0));
+        let composition = pairs.composition(&(0..16).collect::<Vec<_>>());
+        assert_eq!(composition, vec![(0, 0.5), (1, 0.5)]);
+        // Every parameter is plus or minus one
+        assert_eq!(pairs.disorder(&composition), 4.0);
+    }
+
+    #[test]
+    fn targets_need_sites_of_their_kinds() {
+        let lattice = Lattice::bcc(1.0).expand_all(2);
+        let pairs = PairCounts::new(&lattice, 1, |_| Some(0));
+        assert_eq!(
+            pairs.targets(&[("B", "A", -0.5)]).unwrap(),
+            vec![(1, 0, -0.5)]
+        );
+        assert!(pairs.targets(&[("A", "Bb", 0.0)]).is_err());
+        // Only the A sites are left, so B has no concentration
+        let pairs = pairs.within(&(0..16).step_by(2).collect::<Vec<_>>());
+        assert!(pairs.targets(&[("A", "A", 0.0)]).is_ok());
+        assert!(pairs.targets(&[("A", "B", 0.0)]).is_err());
+    }
+}
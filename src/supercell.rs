@@ -0,0 +1,224 @@
+//! A lazy view of an expanded lattice
+
+use crate::{
+    edge::Edge,
+    lattice::Lattice,
+    site::SiteRef,
+    util::{IDENTITY, scale},
+};
+use serde::ser::{Serialize, SerializeStruct, Serializer};
+
+/// A lattice expanded along the three axes, whose sites and edges are
+/// computed on the fly from the unit cell instead of being stored.
+///
+/// The sites and edges come in the same order as in
+/// `lattice.expand(x, y, z)`, so writing a supercell gives the same output as
+/// writing the expanded lattice, but the memory needed does not grow with the
+/// number of cells.
+///
+/// # Examples
+///
+/// ```rust
+/// use vegas_lattice::Lattice;
+///
+/// let lattice = Lattice::bcc(1.0);
+/// let supercell = lattice.supercell(2, 3, 4);
+///
+/// assert_eq!(supercell.size(), (2.0, 3.0, 4.0));
+/// assert_eq!(supercell.site_count(), 48);
+/// assert_eq!(supercell.sites().nth(2).unwrap().position(), (1.0, 0.0, 0.0));
+/// assert_eq!(
+///     serde_json::to_string(&supercell).unwrap(),
+///     serde_json::to_string(&lattice.clone().expand(2, 3, 4)).unwrap(),
+/// );
+/// ```
+#[derive(Clone, Copy, Debug)]
+pub struct Supercell<'a> {
+    lattice: &'a Lattice,
+    amounts: (usize, usize, usize),
+}
+
+impl<'a> Supercell<'a> {
+    pub(crate) fn new(lattice: &'a Lattice, x: usize, y: usize, z: usize) -> Self {
+        Supercell {
+            lattice,
+            amounts: (x, y, z),
+        }
+    }
+
+    /// Get the unit cell the supercell is made of
+    pub fn lattice(&self) -> &'a Lattice {
+        self.lattice
+    }
+
+    /// Get the number of cells along each axis
+    pub fn amounts(&self) -> (usize, usize, usize) {
+        self.amounts
+    }
+
+    /// Get the size of the supercell
+    pub fn size(&self) -> (f64, f64, f64) {
+        let (a, b, c) = self.lattice.size();
+        let (x, y, z) = self.amounts;
+        (a * x as f64, b * y as f64, c * z as f64)
+    }
+
+    /// Get the directions of the lattice vectors
+    pub fn vectors(&self) -> [(f64, f64, f64); 3] {
+        self.lattice.vectors()
+    }
+
+    /// Get the cell vectors of the supercell
+    pub fn cell(&self) -> [(f64, f64, f64); 3] {
+        let vectors = self.vectors();
+        let (a, b, c) = self.size();
+        [
+            scale(vectors[0], a),
+            scale(vectors[1], b),
+            scale(vectors[2], c),
+        ]
+    }
+
+    fn cells(&self) -> usize {
+        self.amounts.0 * self.amounts.1 * self.amounts.2
+    }
+
+    /// Iterates over the indices of the cells, in the order of the sites
+    fn indices(&self) -> impl Iterator<Item = (usize, usize, usize)> + use<> {
+        let (nx, ny, nz) = self.amounts;
+        (0..nz).flat_map(move |k| (0..ny).flat_map(move |j| (0..nx).map(move |i| (i, j, k))))
+    }
+
+    /// Get the number of sites of the supercell
+    pub fn site_count(&self) -> usize {
+        self.cells() * self.lattice.sites().len()
+    }
+
+    /// Get the number of edges of the supercell
+    pub fn edge_count(&self) -> usize {
+        self.cells() * self.lattice.edges().len()
+    }
+
+    /// Iterates over the sites of the supercell
+    pub fn sites(&self) -> impl Iterator<Item = SiteRef<'a>> + 'a {
+        let (a, b, c) = self.lattice.size();
+        let [u, v, w] = self.lattice.vectors();
+        let sites = self.lattice.sites();
+        self.indices().flat_map(move |(i, j, k)| {
+            sites.iter().enumerate().map(move |(basis, site)| {
+                site.or_basis(basis)
+                    .move_along(u, i as f64 * a)
+                    .move_along(v, j as f64 * b)
+                    .move_along(w, k as f64 * c)
+            })
+        })
+    }
+
+    /// Iterates over the edges of the supercell
+    pub fn edges(&self) -> impl Iterator<Item = Edge> + 'a {
+        let (nx, ny, nz) = self.amounts;
+        let n = self.lattice.sites().len();
+        let edges = self.lattice.edges();
+        self.indices().flat_map(move |(i, j, k)| {
+            edges.iter().map(move |edge| {
+                edge.clone()
+                    .move_x(i, n, nx)
+                    .move_y(j, n * nx, ny)
+                    .move_z(k, n * nx * ny, nz)
+            })
+        })
+    }
+
+    /// Builds the expanded lattice, this is the same as `Lattice::expand`
+    pub fn to_lattice(&self) -> Lattice {
+        self.lattice
+            .clone()
+            .expand(self.amounts.0, self.amounts.1, self.amounts.2)
+    }
+}
+
+/// Serializes the sites of a supercell as a sequence
+struct Sites<'a, 'b>(&'b Supercell<'a>);
+
+impl Serialize for Sites<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.sites())
+    }
+}
+
+/// Serializes the edges of a supercell as a sequence
+struct Edges<'a, 'b>(&'b Supercell<'a>);
+
+impl Serialize for Edges<'_, '_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(self.0.edges())
+    }
+}
+
+/// A supercell serializes exactly like the expanded lattice
+impl Serialize for Supercell<'_> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let orthogonal = self.vectors() == IDENTITY;
+        let metadata = self.lattice.metadata();
+        let fields = 3 + usize::from(!orthogonal) + usize::from(!metadata.is_empty());
+        let mut state = serializer.serialize_struct("Lattice", fields)?;
+        state.serialize_field("size", &self.size())?;
+        if orthogonal {
+            state.skip_field("vectors")?;
+        } else {
+            state.serialize_field("vectors", &self.vectors())?;
+        }
+        state.serialize_field("sites", &Sites(self))?;
+        state.serialize_field("edges", &Edges(self))?;
+        if metadata.is_empty() {
+            state.skip_field("metadata")?;
+        } else {
+            state.serialize_field("metadata", metadata)?;
+        }
+        state.end()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use crate::Lattice;
+
+    #[test]
+    fn supercell_matches_expansion() {
+        let lattice = Lattice::hcp(1.0, 1.6).try_with_neighbor_shells(2).unwrap();
+        let supercell = lattice.supercell(3, 2, 4);
+        let expanded = lattice.clone().expand(3, 2, 4);
+        assert_eq!(supercell.size(), expanded.size());
+        assert_eq!(supercell.cell(), expanded.cell());
+        assert_eq!(supercell.site_count(), expanded.sites().len());
+        assert_eq!(supercell.edge_count(), expanded.edges().len());
+        for (site, other) in supercell.sites().zip(expanded.sites()) {
+            assert_eq!(site.kind(), other.kind());
+            assert_eq!(site.position(), other.position());
+        }
+        for (edge, other) in supercell.edges().zip(expanded.edges()) {
+            assert_eq!(edge.source(), other.source());
+            assert_eq!(edge.target(), other.target());
+            assert_eq!(edge.delta(), other.delta());
+        }
+    }
+
+    #[test]
+    fn supercell_serializes_like_the_expansion() {
+        let lattice = Lattice::fcc(1.0).with_metadata("seeds", "alloy:1");
+        let supercell = lattice.supercell(2, 1, 3);
+        assert_eq!(
+            serde_json::to_string(&supercell).unwrap(),
+            serde_json::to_string(&supercell.to_lattice()).unwrap()
+        );
+    }
+
+    #[test]
+    fn empty_supercell() {
+        let lattice = Lattice::sc(1.0);
+        let supercell = lattice.supercell(0, 2, 2);
+        assert_eq!(supercell.site_count(), 0);
+        assert_eq!(supercell.sites().count(), 0);
+        assert_eq!(supercell.edges().count(), 0);
+    }
+}
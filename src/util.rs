@@ -22,15 +22,24 @@ impl Axis {
     }
 }
 
+/// Remainder and quotient of a division rounding towards negative infinity,
+/// the remainder is always in `0..modulus`
 pub(crate) fn python_mod(num: i32, modulus: usize) -> (i32, i32) {
-    if num < 0 {
-        (
-            modulus as i32 + num % modulus as i32,
-            num / modulus as i32 - 1,
-        )
-    } else {
-        (num % modulus as i32, num / modulus as i32)
+    (
+        num.rem_euclid(modulus as i32),
+        num.div_euclid(modulus as i32),
+    )
+}
+
+/// Lists the distinct values in order of first appearance
+pub(crate) fn distinct<T: PartialEq>(values: impl Iterator<Item = T>) -> Vec<T> {
+    let mut distinct = Vec::new();
+    for value in values {
+        if !distinct.contains(&value) {
+            distinct.push(value);
+        }
     }
+    distinct
 }
 
 /// A trait for tagged objects
@@ -45,3 +54,73 @@ pub trait Tagged {
         }
     }
 }
+
+/// A 3D vector represented as a tuple, the same way positions are stored
+pub(crate) type Vector = (f64, f64, f64);
+
+/// The cartesian unit vectors, used as the default lattice vectors
+pub(crate) const IDENTITY: [Vector; 3] = [(1.0, 0.0, 0.0), (0.0, 1.0, 0.0), (0.0, 0.0, 1.0)];
+
+pub(crate) fn add(a: Vector, b: Vector) -> Vector {
+    (a.0 + b.0, a.1 + b.1, a.2 + b.2)
+}
+
+pub(crate) fn sub(a: Vector, b: Vector) -> Vector {
+    (a.0 - b.0, a.1 - b.1, a.2 - b.2)
+}
+
+pub(crate) fn scale(a: Vector, factor: f64) -> Vector {
+    (a.0 * factor, a.1 * factor, a.2 * factor)
+}
+
+pub(crate) fn dot(a: Vector, b: Vector) -> f64 {
+    a.0 * b.0 + a.1 * b.1 + a.2 * b.2
+}
+
+pub(crate) fn cross(a: Vector, b: Vector) -> Vector {
+    (
+        a.1 * b.2 - a.2 * b.1,
+        a.2 * b.0 - a.0 * b.2,
+        a.0 * b.1 - a.1 * b.0,
+    )
+}
+
+pub(crate) fn norm(a: Vector) -> f64 {
+    dot(a, a).sqrt()
+}
+
+/// Determinant of the matrix whose rows are the given vectors
+pub(crate) fn determinant(vectors: &[Vector; 3]) -> f64 {
+    dot(vectors[0], cross(vectors[1], vectors[2]))
+}
+
+/// Reciprocal vectors (without the 2π factor) of the given cell vectors
+///
+/// The fractional coordinates of a position are its dot products with the
+/// reciprocal vectors.
+pub(crate) fn reciprocal(vectors: &[Vector; 3]) -> [Vector; 3] {
+    let volume = determinant(vectors);
+    [
+        scale(cross(vectors[1], vectors[2]), 1.0 / volume),
+        scale(cross(vectors[2], vectors[0]), 1.0 / volume),
+        scale(cross(vectors[0], vectors[1]), 1.0 / volume),
+    ]
+}
+
+#[cfg(test)]
+mod test {
+    use super::python_mod;
+
+    #[test]
+    fn python_mod_rounds_towards_negative_infinity() {
+        assert_eq!(python_mod(5, 2), (1, 2));
+        assert_eq!(python_mod(-3, 2), (1, -2));
+        assert_eq!(python_mod(0, 3), (0, 0));
+    }
+
+    #[test]
+    fn python_mod_wraps_negative_multiples_to_zero() {
+        assert_eq!(python_mod(-1, 1), (0, -1));
+        assert_eq!(python_mod(-4, 2), (0, -2));
+    }
+}
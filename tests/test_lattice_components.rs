@@ -49,3 +49,44 @@ fn lattice_will_fail_for_inconsistent_size() {
     let lattice_result: Result<Lattice, _> = data.parse();
     assert!(lattice_result.is_err());
 }
+
+#[test]
+fn lattice_with_vectors_example() {
+    let data = r#"
+        {
+            "size": [1, 1, 1.6],
+            "vectors": [[1, 0, 0], [-0.5, 0.8660254037844386, 0], [0, 0, 1]],
+            "sites": [
+                {"kind": "Co", "position": [0, 0, 0]}
+            ],
+            "edges": []
+        }
+    "#;
+    let lattice: Lattice = data.parse().unwrap();
+    assert_eq!(lattice.vectors()[1], (-0.5, 0.8660254037844386, 0.0));
+}
+
+#[test]
+fn lattice_will_fail_for_degenerate_vectors() {
+    let data = r#"
+        {
+            "size": [1, 1, 1],
+            "vectors": [[1, 0, 0], [1, 0, 0], [0, 0, 1]],
+            "sites": [],
+            "edges": []
+        }
+    "#;
+    let lattice_result: Result<Lattice, _> = data.parse();
+    assert!(lattice_result.is_err());
+}
+
+#[test]
+fn lattice_can_be_read_from_a_reader() {
+    let data =
+        r#"{"size": [1, 1, 1], "sites": [{"kind": "Fe", "position": [0, 0, 0]}], "edges": []}"#;
+    let lattice = vegas_lattice::io::from_reader_lattice(data.as_bytes()).unwrap();
+    assert_eq!(lattice.sites().len(), 1);
+    let data = r#"{"size": [1, 1, 0], "sites": [], "edges": []}"#;
+    let lattice = vegas_lattice::io::from_reader_lattice(data.as_bytes()).unwrap();
+    assert_eq!(lattice.size(), (1.0, 1.0, 0.0));
+}